@@ -1,26 +1,21 @@
 
-use async_std;
 use lapin::{
-    options::*,  types::FieldTable,  Connection,
+    options::*,  types::FieldTable,  
     ConnectionProperties, Result, message::DeliveryResult
 };
 use tracing::info;
 
-use hello_world::quit_service;
-use hello_world::LOCALHOST;
+use rabbit_common::{logging, LogLevel};
+use rabbit_common::shutdown::{self, InFlight};
 
 #[async_std::main]
 async fn main() -> Result<()> {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
+    logging::init(LogLevel::Info);
 
-    tracing_subscriber::fmt::init();
-
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
 
     // Establish connection
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -41,23 +36,37 @@ async fn main() -> Result<()> {
         .await?;
 
     info!("Channel Consumer created");
-    consumer.set_delegate(move |delivery: DeliveryResult| async move {
-        let delivery = delivery.expect("error caught in in consumer");
-        if let Some((channel, delivery)) = delivery {
-            channel
-                .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
-                .await
-                .expect("failed to ack");
-            let val = std::str::from_utf8(&delivery.data);
-            if let Ok(value) = val {
-
-                println!("[x] Delivered: {}",value);
-            } else {
-                println!("unable to convert raw data from delivery to string");
-            }
-        } 
+    // track the deliveries being worked on, so that shutdown can wait for them
+    let in_flight = InFlight::new();
+    consumer.set_delegate({
+        let in_flight = in_flight.clone();
+        move |delivery: DeliveryResult| {
+            let working = in_flight.start();
+            async move {
+                let _working = working;
+                let delivery = delivery.expect("error caught in in consumer");
+                if let Some((channel, delivery)) = delivery {
+                    channel
+                        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                        .await
+                        .expect("failed to ack");
+                    let val = std::str::from_utf8(&delivery.data);
+                    if let Ok(value) = val {
 
+                        println!("[x] Delivered: {}",value);
+                    } else {
+                        println!("unable to convert raw data from delivery to string");
+                    }
+                } 
+            }
+        }
     })?;
-    
-    Ok(quit_service::prompt())
+
+    // wait for SIGINT, SIGTERM or "q", then stop consuming cleanly
+    shutdown::listen()
+        .expect("unable to install signal handler")
+        .wait()
+        .await;
+    info!("shutting down");
+    shutdown::close(&conn, &channel_b, consumer.tag().as_str(), &in_flight).await
 }
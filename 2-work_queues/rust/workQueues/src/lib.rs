@@ -1,20 +1 @@
-pub const LOCALHOST: &'static str = "amqp://127.0.0.1:5672/%2f";
-pub const QUEUE: &'static str = "work_queues_rust";
-
-pub mod quit_service {
-    use std::io;
-    pub fn prompt() {
-        println!("Type q or exit to quit");
-        loop {
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-               Ok(_goes_into_input_above) => {},
-               Err(_no_updates_is_fine) => {},
-            }
-            let input = input.trim().to_string();
-            if input == "q" || input == "quit" || input == "exit" {
-               return 
-           }
-       }
-    }
-}
\ No newline at end of file
+pub const QUEUE: &str = "work_queues_rust";
@@ -1,23 +1,21 @@
 //use async_amqp::*;
 use lapin::{
     options::*, publisher_confirm::Confirmation, types::FieldTable, 
-    BasicProperties, Connection,
+    BasicProperties, 
     ConnectionProperties, Result, //message::DeliveryResult,
 };
 use tracing::info;
 use std::env;
 
-use work_queues::{LOCALHOST, QUEUE};
+use work_queues::QUEUE;
+use rabbit_common::{logging, LogLevel};
 
 fn setup() {
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info");
-    }
     if env::args().len() < 2 {
         println!("Usage: client <message>");
         std::process::exit(1);
     }
-    tracing_subscriber::fmt::init();
+    logging::init(LogLevel::Info);
 }
 
 
@@ -25,10 +23,10 @@ fn setup() {
 async fn main() -> Result<()> {
     setup();
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
 
   
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -1,16 +1,16 @@
 use lapin::{
-    options::*,  types::FieldTable,  Connection,
+    options::*,  types::FieldTable,  
     ConnectionProperties, Result, message::DeliveryResult
 };
 
 use structopt::StructOpt;
 
-use async_std;
 use tracing::info;
 use std::time;
 
-use work_queues::{LOCALHOST, QUEUE};
-use work_queues::quit_service;
+use work_queues::QUEUE;
+use rabbit_common::{logging, LogLevel};
+use rabbit_common::shutdown::{self, InFlight};
 
 
 #[derive(Debug, StructOpt)]
@@ -27,18 +27,15 @@ struct Opt {
 
 #[async_std::main]
 async fn main() -> Result<()> {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
     // process args
     let opt = Opt::from_args();
 
-    tracing_subscriber::fmt::init();
+    logging::init(LogLevel::Info);
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
 
     // Establish connection
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -64,11 +61,9 @@ async fn main() -> Result<()> {
 
     info!("QOS OPTIONS: {:#?}", qos_options);
 
-    let  consume_options = BasicConsumeOptions::default();
     // exclusive queue 
     //consume_options.exclusive= true;
-    // doesn't need to be mutable anymore
-    let consume_options = consume_options;
+    let consume_options = BasicConsumeOptions::default();
 
     info!("consume options {:#?}", consume_options);
     let  consumer = channel_b
@@ -81,31 +76,43 @@ async fn main() -> Result<()> {
         .await?;
 
     info!("Channel Consumer created");
-    consumer.set_delegate(move |delivery: DeliveryResult| async move {
-        let delivery = delivery.expect("error caught in in consumer");
-        if let Some((channel, delivery)) = delivery {
+    // track the deliveries being worked on, so that shutdown can wait for them
+    let in_flight = InFlight::new();
+    consumer.set_delegate({
+        let in_flight = in_flight.clone();
+        move |delivery: DeliveryResult| {
+            let working = in_flight.start();
+            async move {
+                let _working = working;
+                let delivery = delivery.expect("error caught in in consumer");
+                if let Some((channel, delivery)) = delivery {
             
-            let val = std::str::from_utf8(&delivery.data);
+                    let val = std::str::from_utf8(&delivery.data);
             
-            if let Ok(value) = val {
-                println!("[x] Start:  {}",value);
-                let sleep_duration = value.matches('.').count();
-                if sleep_duration > 0 {
-                async_std::task::sleep(time::Duration::new(sleep_duration as u64,0)).await;
-            }
-                println!("[x] Finish: {}",value);
-            } else {
-                println!("unable to convert raw data from delivery to string");
+                    if let Ok(value) = val {
+                        println!("[x] Start:  {}",value);
+                        let sleep_duration = value.matches('.').count();
+                        if sleep_duration > 0 {
+                        async_std::task::sleep(time::Duration::new(sleep_duration as u64,0)).await;
+                    }
+                        println!("[x] Finish: {}",value);
+                    } else {
+                        println!("unable to convert raw data from delivery to string");
+                    }
+                    channel
+                        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                        .await
+                        .expect("failed to ack");
+                } 
             }
-            channel
-                .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
-                .await
-                .expect("failed to ack");
-        } 
-
+        }
     })?;
-    
-    Ok(quit_service::prompt())
-   
-}
 
+    // wait for SIGINT, SIGTERM or "q", then stop consuming cleanly
+    shutdown::listen()
+        .expect("unable to install signal handler")
+        .wait()
+        .await;
+    info!("shutting down");
+    shutdown::close(&conn, &channel_b, consumer.tag().as_str(), &in_flight).await
+}
@@ -1,23 +1,21 @@
 //use async_amqp::*;
 use lapin::{
     options::*, publisher_confirm::Confirmation, types::FieldTable, 
-    BasicProperties, Connection, 
+    BasicProperties, 
     ConnectionProperties, Result, 
 };
 use tracing::info;
 use std::env;
 
-use pubsub::{LOCALHOST, EXCHANGE, EXCHANGE_TYPE, ROUTING_KEY};
+use pubsub::{EXCHANGE, EXCHANGE_TYPE, ROUTING_KEY};
+use rabbit_common::{logging, LogLevel};
 
 fn setup() {
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info");
-    }
     if env::args().len() < 2 {
         println!("Usage: client <message>");
         std::process::exit(1);
     }
-    tracing_subscriber::fmt::init();
+    logging::init(LogLevel::Info);
 }
 
 
@@ -25,10 +23,10 @@ fn setup() {
 async fn main() -> Result<()> {
     setup();
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
 
   
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -38,7 +36,7 @@ async fn main() -> Result<()> {
     let channel_a = conn.create_channel().await?;
     info!("created channel");
 
-    let exchange = channel_a.exchange_declare(
+    channel_a.exchange_declare(
         EXCHANGE,
         EXCHANGE_TYPE,
         ExchangeDeclareOptions::default(),
@@ -49,7 +47,7 @@ async fn main() -> Result<()> {
     // should use std::debug to print. And `tracing` uses `%`
     // prefix to indicate that it should use std::display. Just
     // so you know....
-    info!(?exchange, "Declared exchange: {}", &EXCHANGE);
+    info!("Declared exchange: {}", EXCHANGE);
 
     let msg = env::args().skip(1).collect::<Vec<_>>().join(" ");
     
@@ -1,16 +1,16 @@
 use lapin::{
-    options::*,  types::FieldTable,  Connection,
+    options::*,  types::FieldTable,  
     ConnectionProperties, Result, message::DeliveryResult
 };
 
 use structopt::StructOpt;
 
-use async_std;
 use tracing::info;
 use std::time;
 
-use pubsub::{LOCALHOST, QUEUE, EXCHANGE, EXCHANGE_TYPE, ROUTING_KEY};
-use pubsub::quit_service;
+use pubsub::{QUEUE, EXCHANGE, EXCHANGE_TYPE, ROUTING_KEY};
+use rabbit_common::{logging, LogLevel};
+use rabbit_common::shutdown::{self, InFlight};
 
 
 #[derive(Debug, StructOpt)]
@@ -26,11 +26,7 @@ struct Opt {
 
 
 fn initialize() {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
-    
-    tracing_subscriber::fmt::init();
+    logging::init(LogLevel::Info);
 }
 #[async_std::main]
 async fn main() -> Result<()> {
@@ -38,9 +34,9 @@ async fn main() -> Result<()> {
     // process args
     let opt = Opt::from_args();
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
     // Establish connection
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -53,17 +49,16 @@ async fn main() -> Result<()> {
     info!("Channel created");
     
     // Create the exchange from the channel
-    let exchange = channel_b.exchange_declare(
+    channel_b.exchange_declare(
         EXCHANGE,
         EXCHANGE_TYPE,
         ExchangeDeclareOptions::default(),
         FieldTable::default()
     ).await?;
-    info!(?exchange, "Declared exchange: {}", &EXCHANGE);
+    info!("Declared exchange: {}", EXCHANGE);
 
     // declare the queue
-    let mut queue_opts = QueueDeclareOptions::default();
-    queue_opts.exclusive = true;
+    let queue_opts = QueueDeclareOptions{exclusive: true, ..Default::default()};
     let queue = channel_b
         .queue_declare(
             QUEUE,
@@ -75,7 +70,7 @@ async fn main() -> Result<()> {
     // bind the queue to the exchange
     channel_b.queue_bind(
         // queue name
-        &queue.name().as_str(),
+        queue.name().as_str(),
         EXCHANGE,
         ROUTING_KEY,
         QueueBindOptions::default(),
@@ -93,7 +88,7 @@ async fn main() -> Result<()> {
     // create a consumer
     let  consumer = channel_b
         .basic_consume(
-            &queue.name().as_str(),
+            queue.name().as_str(),
             "my_consumer",
             BasicConsumeOptions::default(),
             FieldTable::default(),
@@ -102,30 +97,43 @@ async fn main() -> Result<()> {
     info!("Channel Consumer created");
     
     // register the delegate (callback) with the consumer
-    consumer.set_delegate(move |delivery: DeliveryResult| async move {
-        let delivery = delivery.expect("error caught in in consumer");
-        if let Some((channel, delivery)) = delivery {
+    // track the deliveries being worked on, so that shutdown can wait for them
+    let in_flight = InFlight::new();
+    consumer.set_delegate({
+        let in_flight = in_flight.clone();
+        move |delivery: DeliveryResult| {
+            let working = in_flight.start();
+            async move {
+                let _working = working;
+                let delivery = delivery.expect("error caught in in consumer");
+                if let Some((channel, delivery)) = delivery {
             
-            let val = std::str::from_utf8(&delivery.data);
+                    let val = std::str::from_utf8(&delivery.data);
             
-            if let Ok(value) = val {
-                println!("[x] Start:  {}",value);
-                let sleep_duration = value.matches('.').count();
-                if sleep_duration > 0 {
-                async_std::task::sleep(time::Duration::new(sleep_duration as u64,0)).await;
-            }
-                println!("[x] Finish: {}",value);
-            } else {
-                println!("unable to convert raw data from delivery to string");
+                    if let Ok(value) = val {
+                        println!("[x] Start:  {}",value);
+                        let sleep_duration = value.matches('.').count();
+                        if sleep_duration > 0 {
+                        async_std::task::sleep(time::Duration::new(sleep_duration as u64,0)).await;
+                    }
+                        println!("[x] Finish: {}",value);
+                    } else {
+                        println!("unable to convert raw data from delivery to string");
+                    }
+                    channel
+                        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                        .await
+                        .expect("failed to ack");
+                } 
             }
-            channel
-                .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
-                .await
-                .expect("failed to ack");
-        } 
+        }
     })?;
-    
-    Ok(quit_service::prompt())
-   
-}
 
+    // wait for SIGINT, SIGTERM or "q", then stop consuming cleanly
+    shutdown::listen()
+        .expect("unable to install signal handler")
+        .wait()
+        .await;
+    info!("shutting down");
+    shutdown::close(&conn, &channel_b, consumer.tag().as_str(), &in_flight).await
+}
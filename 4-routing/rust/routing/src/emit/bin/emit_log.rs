@@ -2,7 +2,7 @@ use anyhow::anyhow;
 use anyhow::Result as AnyhowResult;
 use lapin::{
     options::*, publisher_confirm::Confirmation, types::FieldTable, 
-    BasicProperties, Connection, 
+    BasicProperties, 
     ConnectionProperties, Result, 
 };
 use std::env;
@@ -10,15 +10,12 @@ use std::convert::AsRef;
 use std::str::FromStr;
 use tracing::{info,error};
 
-use routing::{LOCALHOST, EXCHANGE, EXCHANGE_TYPE, LogLevel};
+use routing::{EXCHANGE, EXCHANGE_TYPE};
+use rabbit_common::{logging, LogLevel};
 
 // set up default logging level and initialize tracing
 fn setup() {
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info");
-    }
-    
-    tracing_subscriber::fmt::init();
+    logging::init(LogLevel::Info);
 }
 
 fn _parse_args() -> AnyhowResult<(LogLevel, String)> {
@@ -40,10 +37,9 @@ fn _parse_args() -> AnyhowResult<(LogLevel, String)> {
 }
 
 fn parse_args() -> (LogLevel, String) {
-    let args = _parse_args();
-    match args {
+    match _parse_args() {
         Ok(result) => result,
-        Err(err) => {error!("Unable to parse arguments");  
+        Err(err) => {error!("Unable to parse arguments: {}", err);std::process::exit(1) ;},
     }
 }
 
@@ -51,16 +47,11 @@ fn parse_args() -> (LogLevel, String) {
 async fn main() -> Result<()> {
     setup();
 
-    let args = parse_args();
-    if args.is_err() {
-        error!("{:#?}", args);
-        std::process::exit(1);
-    }
-    let (routing_key, msg) = args.unwrap();
+    let (routing_key, msg) = parse_args();
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
   
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -70,13 +61,13 @@ async fn main() -> Result<()> {
     let channel_a = conn.create_channel().await?;
     info!("created channel");
 
-    let exchange = channel_a.exchange_declare(
+    channel_a.exchange_declare(
         EXCHANGE,
         EXCHANGE_TYPE,
         ExchangeDeclareOptions::default(),
         FieldTable::default()
     ).await?;
-    info!(?exchange, "Declared exchange: {}", &EXCHANGE);
+    info!("Declared exchange: {}", EXCHANGE);
     
     let confirm = channel_a
         .basic_publish(
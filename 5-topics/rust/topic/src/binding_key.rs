@@ -3,21 +3,31 @@
 //! # BindingKey
 //! struct which represents a BindingKey
 //! 
-use ::anyhow::anyhow;
 use anyhow::Error as AnyhowError;
 use std::fmt;
 use std::str::FromStr;
 
 use crate::LogLevel;
-use crate::Location;
+use crate::{Location, LocationRegistry};
+use crate::location::registry;
+use crate::MAX_KEY_BYTES;
 use std::convert::From;
 use crate::RoutingKey;
+use crate::{BindingPattern, PatternWord};
 
 /// a location aware log level, specified as a dot delimited
-/// <location>.<level>
+/// <location>.<level>, or <location>.<service>.<level>
 /// Either location of level or both may be specified as "*" to 
-/// indicate any
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// indicate any, as may the service. Any other binding key (eg `#.error` or
+/// `portland.#`) is held as a general `Pattern`.
+///
+/// As with the topic exchange, `*.warn`, `portland.*` and `portland.warn`
+/// only match routing keys without a service, and `*.*.warn` only those with
+/// one. Use `#.warn` or `portland.#` to match both. Likewise, `*` does not
+/// match a nested location (eg `vancouver.stage1`), which spans several words.
+/// The exception is `*.*`, which is kept as `Any` for backward compatibility,
+/// and so binds `#`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum BindingKey {
     /// BindingKey
     Pair{
@@ -28,21 +38,175 @@ pub enum BindingKey {
     AnyLevel(Location),
     /// Any Location but specific LogLevel
     AnyLoc(LogLevel),
-    /// Any Location and LogLevel
+    /// Any routing key at all
     Any,
+    /// <location>.<service>.<level>, where `None` is "*"
+    Service{
+    location: Option<Location>,
+    service: Option<String>,
+    level: Option<LogLevel>
+    },
+    /// Any other sequence of words, `*` and `#`
+    Pattern(BindingPattern),
 }
 
 impl BindingKey {
-    /// Does the variant of BindingKey represent a distinct pair of Location and Level?
+    /// Does the variant of BindingKey represent a distinct pair of Location and Level
+    /// (and service, if any)?
     pub fn is_specific(&self) -> bool {
-        if let Self::Pair{..} = self {true} else {false} 
+        match self {
+            Self::Pair{..} => true,
+            Self::Service{location, service, level} => location.is_some() && service.is_some() && level.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Would a message published with the supplied RoutingKey be delivered to a
+    /// queue bound with this BindingKey? See `topic_matches` for the equivalent
+    /// on raw strings.
+    pub fn matches(&self, key: &RoutingKey) -> bool {
+        match self {
+            Self::Any => true,
+            // a location may span several words, so leave it to the pattern to
+            // count them, as the broker does
+            _ => self.to_pattern().matches(key),
+        }
+    }
+
+    /// Parse a BindingKey, validating any location it names against the
+    /// supplied registry. A key which starts with a literal word must start
+    /// with a known location, as every routing key does.
+    pub fn parse(s: &str, registry: &LocationRegistry) -> Result<Self, AnyhowError> {
+        let pattern = BindingPattern::from_str(s)?;
+        let words = literals(pattern.words());
+        if !words.is_empty() && registry.prefixes(&words).is_empty() {
+            return Err(registry.unknown(words[0]));
+        }
+        Ok(Self::from_pattern(pattern, registry))
+    }
+
+    /// The BindingKey as a general BindingPattern
+    pub fn to_pattern(&self) -> BindingPattern {
+        use PatternWord::*;
+        // a nested location spans several words
+        let (location, rest) = match self {
+            Self::Pair{location, level} => (location_words(location), vec![literal(level)]),
+            Self::AnyLevel(location) => (location_words(location), vec![Star]),
+            Self::AnyLoc(level) => (vec![Star], vec![literal(level)]),
+            Self::Any => (Vec::new(), vec![Hash]),
+            Self::Service{location, service, level} => (
+                location.as_ref().map_or_else(|| vec![Star], location_words),
+                vec![service.as_ref().map_or(Star, literal), level.as_ref().map_or(Star, literal)],
+            ),
+            Self::Pattern(pattern) => return pattern.clone(),
+        };
+        BindingPattern::from_words([location, rest].concat())
+    }
+
+    // narrow a general pattern to one of the typed shortcuts, where it has the
+    // shape of one
+    fn from_pattern(pattern: BindingPattern, registry: &LocationRegistry) -> Self {
+        use PatternWord::*;
+        let words = pattern.words();
+        // `*.*` matched every key before keys could name a service, so it is
+        // still taken to mean `Any` (and binds `#`) rather than dropping them
+        if let [Hash] | [Star, Star] = words {
+            return Self::Any;
+        }
+        // the key starts with "*", or with the words of a location
+        let candidates = match words.first() {
+            Some(Star) => vec![(None, 1)],
+            _ => registry.prefixes(&literals(words)).into_iter()
+                .map(|(location, len)| (Some(location), len))
+                .collect(),
+        };
+        for (location, len) in candidates {
+            let key = match (location, &words[len..]) {
+                (Some(location), [Literal(level)]) => LogLevel::from_str(level).ok()
+                    .map(|level| Self::Pair{location, level}),
+                (Some(location), [Star]) => Some(Self::AnyLevel(location)),
+                (None, [Literal(level)]) => LogLevel::from_str(level).ok().map(Self::AnyLoc),
+                (location, [service, level]) => match (typed::<String>(service), typed::<LogLevel>(level)) {
+                    (Some(service), Some(level)) => Some(Self::Service{location, service, level}),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match key {
+                // spelling out an alias may lengthen the key
+                Some(key) if key.to_string().len() <= MAX_KEY_BYTES => return key,
+                _ => {}
+            }
+        }
+        Self::Pattern(canonical(pattern, registry))
+    }
+}
+
+// a word of a binding key which must match exactly
+fn literal<T: AsRef<str>>(word: &T) -> PatternWord {
+    PatternWord::Literal(word.as_ref().to_string())
+}
+
+// the words of a location, spelled out in full
+fn location_words(location: &Location) -> Vec<PatternWord> {
+    location.name().split('.').map(|word| PatternWord::Literal(word.to_string())).collect()
+}
+
+// the literal words at the start of a pattern
+fn literals(words: &[PatternWord]) -> Vec<&str> {
+    words.iter()
+        .take_while(|word| matches!(word, PatternWord::Literal(_)))
+        .map(PatternWord::as_str)
+        .collect()
+}
+
+// `*` is any (`Some(None)`), a word which parses is that value, and anything
+// else does not fit a typed shortcut
+fn typed<T: FromStr>(word: &PatternWord) -> Option<Option<T>> {
+    match word {
+        PatternWord::Star => Some(None),
+        PatternWord::Literal(word) => T::from_str(word).ok().map(Some),
+        PatternWord::Hash => None,
+    }
+}
+
+// routing keys always start with a location and end with a level, spelled out
+// in full. Spell out any alias (eg `pd` or `err`) in those positions, so that
+// the pattern matches them.
+fn canonical(pattern: BindingPattern, registry: &LocationRegistry) -> BindingPattern {
+    let mut words = pattern.words().to_vec();
+    let mut location_len = 0;
+    if let Some((location, len)) = registry.prefixes(&literals(&words)).into_iter().next() {
+        let location_words = location_words(&location);
+        location_len = location_words.len();
+        words.splice(..len, location_words);
     }
+    let last = words.len().saturating_sub(1);
+    if last > 0 && last >= location_len {
+        if let PatternWord::Literal(word) = &mut words[last] {
+            if let Ok(level) = LogLevel::from_str(word) {
+                *word = level.as_ref().to_string();
+            }
+        }
+    }
+    // spelling out a location may make the key too long
+    BindingPattern::new(words).unwrap_or(pattern)
 }
 
 impl From<RoutingKey> for BindingKey {
     fn from(k: RoutingKey) -> Self {
-        let RoutingKey{location, level} = k;
-        BindingKey::Pair{location, level}
+        match k {
+            RoutingKey{location, service: None, level} => BindingKey::Pair{location, level},
+            RoutingKey{location, service, level} => {
+                BindingKey::Service{location: Some(location), service, level: Some(level)}
+            }
+        }
+    }
+}
+
+impl From<BindingPattern> for BindingKey {
+    fn from(pattern: BindingPattern) -> Self {
+        Self::from_pattern(pattern, registry())
     }
 }
 
@@ -50,32 +214,7 @@ impl FromStr for BindingKey {
     type Err = AnyhowError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let pieces = s.split(".").collect::<Vec<_>>();
-        if pieces.len() > 2 {
-            return Err(anyhow!("malformed input: {}", s));
-        }
-        if pieces.len() <2 {
-            if pieces[0] == "#" {
-                return Ok(Self::Any);
-            } 
-            return Err(anyhow!("malformed input: {}", s));
-        }
-        if pieces[0] == "*" {
-            if pieces[1] == "*" {
-                return Ok(Self::Any)
-            }
-            let level = LogLevel::from_str(pieces[1]).map_err(|_| anyhow!("malfomed level: {}", pieces[1]) )?;
-     
-            return Ok(Self::AnyLoc(level));
-        }
-
-        let location = Location::from_str(pieces[0]).map_err(|_| anyhow!("malformed location: {}", pieces[0]))?;
-        if pieces[1] == "*" {
-            return Ok(Self::AnyLevel(location));
-        }
-
-        let level = LogLevel::from_str(pieces[1]).map_err(|_| anyhow!("malfomed level: {}", pieces[1]) )?;
-        return Ok(Self::Pair{location, level})
+        Self::parse(s, registry())
     }
 }
 
@@ -87,6 +226,207 @@ impl fmt::Display for BindingKey {
             Self::AnyLevel(loc) => write!(f,"{}.*", loc.as_ref()),
             Self::AnyLoc(level) => write!(f,"*.{}", level.as_ref()),
             Self::Any => write!(f, "#"),
+            Self::Service{location, service, level} => write!(f, "{}.{}.{}",
+                location.as_ref().map_or("*", AsRef::as_ref),
+                service.as_deref().unwrap_or("*"),
+                level.as_ref().map_or("*", AsRef::as_ref),
+            ),
+            Self::Pattern(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{topic_matches, MAX_KEY_BYTES};
+    use std::convert::TryFrom;
+
+    fn location(name: &str) -> Location {
+        Location::from_str(name).unwrap()
+    }
+
+    fn routing_keys() -> Vec<RoutingKey> {
+        let mut keys = Vec::new();
+        for location in &["playa", "vancouver", "portland", "montreal"] {
+            for level in &["debug", "info", "warn", "error"] {
+                keys.push(RoutingKey::from_str(&format!("{}.{}", location, level)).unwrap());
+            }
+        }
+        keys
+    }
+
+    // the same keys, also sent from the render and comp services
+    fn service_routing_keys() -> Vec<RoutingKey> {
+        let mut keys = routing_keys();
+        for key in routing_keys() {
+            for service in &["render", "comp"] {
+                keys.push(RoutingKey::from_str(&format!("{}.{}.{}", key.location.as_ref(), service, key.level.as_ref())).unwrap());
+            }
+        }
+        keys
+    }
+
+    // the receivers from the README: warnings and errors regardless of source,
+    // everything from portland, and everything
+    #[test]
+    fn matches_readme_examples() {
+        let warn_or_error = [BindingKey::from_str("*.warn").unwrap(), BindingKey::from_str("*.error").unwrap()];
+        let portland = BindingKey::from_str("portland.*").unwrap();
+        let all = BindingKey::from_str("#").unwrap();
+        for key in routing_keys() {
+            assert_eq!(
+                warn_or_error.iter().any(|binding| binding.matches(&key)),
+                key.level == LogLevel::Warn || key.level == LogLevel::Error,
+                "{}", key
+            );
+            assert_eq!(portland.matches(&key), key.location == location("portland"), "{}", key);
+            assert!(all.matches(&key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn matches_given_pair_behaves_like_direct() {
+        let binding = BindingKey::from_str("pd.warning").unwrap();
+        for key in routing_keys() {
+            assert_eq!(binding.matches(&key), key.to_string() == "portland.warn", "{}", key);
+        }
+    }
+
+    #[test]
+    fn matches_agrees_with_topic_matches() {
+        let bindings = [
+            "#", "*.*", "*.info", "montreal.*", "playa.debug", "bc.err", "#.error", "pd.#", "*.#.warn",
+            "*.*.*", "*.render.*", "portland.*.warn", "*.comp.err", "mt.render.info", "*.*.error",
+        ];
+        for binding in bindings.iter().map(|b| BindingKey::from_str(b).unwrap()) {
+            for key in service_routing_keys() {
+                assert_eq!(
+                    binding.matches(&key),
+                    topic_matches(&binding.to_string(), &key.to_string()),
+                    "{} against {}", binding, key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn from_str_given_shortcut_shapes_yields_typed_variants() {
+        assert_eq!(BindingKey::from_str("#").unwrap(), BindingKey::Any);
+        // `*.*` predates services, and still receives keys naming one
+        assert_eq!(BindingKey::from_str("*.*").unwrap(), BindingKey::Any);
+        assert_eq!(BindingKey::from_str("*.*").unwrap().to_string(), "#");
+        assert_eq!(BindingKey::from_str("*.err").unwrap(), BindingKey::AnyLoc(LogLevel::Error));
+        assert_eq!(BindingKey::from_str("mt.*").unwrap(), BindingKey::AnyLevel(location("montreal")));
+        assert!(BindingKey::from_str("playa.info").unwrap().is_specific());
+    }
+
+    #[test]
+    fn from_str_given_service_shapes_yields_service() {
+        assert_eq!(
+            BindingKey::from_str("bc.render.err").unwrap(),
+            BindingKey::Service{location: Some(location("vancouver")), service: Some("render".to_string()), level: Some(LogLevel::Error)}
+        );
+        assert_eq!(
+            BindingKey::from_str("*.render.*").unwrap(),
+            BindingKey::Service{location: None, service: Some("render".to_string()), level: None}
+        );
+        let key = BindingKey::from_str("pd.*.warning").unwrap();
+        assert_eq!(key, BindingKey::Service{location: Some(location("portland")), service: None, level: Some(LogLevel::Warn)});
+        assert_eq!(key.to_string(), "portland.*.warn");
+        assert!(!key.is_specific());
+        assert!(BindingKey::from_str("playa.comp.info").unwrap().is_specific());
+    }
+
+    #[test]
+    fn matches_given_service() {
+        let render = BindingKey::from_str("*.render.*").unwrap();
+        let warn = BindingKey::from_str("*.warn").unwrap();
+        for key in service_routing_keys() {
+            assert_eq!(render.matches(&key), key.service.as_deref() == Some("render"), "{}", key);
+            assert_eq!(warn.matches(&key), key.service.is_none() && key.level == LogLevel::Warn, "{}", key);
+        }
+    }
+
+    #[test]
+    fn routing_key_round_trips_through_binding_key() {
+        for key in service_routing_keys() {
+            let binding = BindingKey::from(RoutingKey::from_str(&key.to_string()).unwrap());
+            assert!(binding.is_specific());
+            assert_eq!(binding.to_string(), key.to_string());
+            assert_eq!(RoutingKey::try_from(binding).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn to_pattern_spells_out_the_key() {
+        for input in &["#", "*.info", "mt.*", "playa.debug", "bc.render.err", "*.comp.*", "pd.#.err"] {
+            let key = BindingKey::from_str(input).unwrap();
+            assert_eq!(key.to_pattern().to_string(), key.to_string(), "{}", input);
+        }
+        // built by hand, so never checked against the length limit
+        let service = "s".repeat(MAX_KEY_BYTES);
+        let key = BindingKey::Service{location: None, service: Some(service), level: None};
+        assert_eq!(key.to_pattern().words().len(), 3);
+    }
+
+    #[test]
+    fn from_str_given_general_pattern_yields_pattern() {
+        for (input, expected) in &[
+            ("#.error", "#.error"),
+            ("portland.#", "portland.#"),
+            ("*.*.error.*", "*.*.error.*"),
+            ("pd.#.err", "portland.#.error"),
+        ] {
+            let key = BindingKey::from_str(input).unwrap();
+            assert!(matches!(key, BindingKey::Pattern(_)), "{}", input);
+            assert_eq!(key.to_string(), *expected);
+        }
+    }
+
+    #[test]
+    fn from_str_given_malformed_key_fails() {
+        assert!(BindingKey::from_str("portland.warn*").is_err());
+        assert!(BindingKey::from_str(&vec!["portland"; 40].join(".")).is_err());
+    }
+
+    #[test]
+    fn from_str_given_unknown_location_fails() {
+        for input in &["nowhere.*", "nowhere.render.warn", "nowhere.#"] {
+            let err = BindingKey::from_str(input).unwrap_err();
+            assert!(err.to_string().contains("Known locations: playa (dd)"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn parse_given_nested_location() {
+        let registry = LocationRegistry::from_toml_str(
+            "[[locations]]\nname = \"vancouver\"\n[[locations]]\nname = \"vancouver.stage1\"\naliases = [\"bc1\"]"
+        ).unwrap();
+        let stage1 = registry.get("bc1").unwrap().clone();
+        let key = BindingKey::parse("bc1.*", &registry).unwrap();
+        assert_eq!(key, BindingKey::AnyLevel(stage1.clone()));
+        assert_eq!(key.to_string(), "vancouver.stage1.*");
+        assert_eq!(
+            BindingKey::parse("vancouver.stage1.render.err", &registry).unwrap(),
+            BindingKey::Service{location: Some(stage1), service: Some("render".to_string()), level: Some(LogLevel::Error)}
+        );
+        assert_eq!(BindingKey::parse("bc1.#", &registry).unwrap().to_string(), "vancouver.stage1.#");
+
+        // `*` spans a single word, so does not reach into nested locations
+        let stage1_warn = RoutingKey::parse("bc1.warn", &registry).unwrap();
+        assert!(!BindingKey::parse("*.warn", &registry).unwrap().matches(&stage1_warn));
+        assert!(BindingKey::parse("vancouver.#", &registry).unwrap().matches(&stage1_warn));
+        assert!(BindingKey::parse("*.*.warn", &registry).unwrap().matches(&stage1_warn));
+    }
+
+    #[test]
+    fn matches_given_pattern() {
+        let errors = BindingKey::from_str("#.error").unwrap();
+        let portland = BindingKey::from_str("pd.#").unwrap();
+        for key in routing_keys() {
+            assert_eq!(errors.matches(&key), key.level == LogLevel::Error, "{}", key);
+            assert_eq!(portland.matches(&key), key.location == location("portland"), "{}", key);
         }
     }
 }
@@ -0,0 +1,149 @@
+//! binding_pattern
+//!
+//! # BindingPattern
+//! A binding key of any shape: a dot delimited sequence of literal words, `*`
+//! (exactly one word) and `#` (zero or more words).
+use anyhow::anyhow;
+use anyhow::Error as AnyhowError;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::matching::{words, words_match};
+use crate::{RoutingKey, MAX_KEY_BYTES};
+
+/// A single word of a BindingPattern
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PatternWord {
+    /// A word which must match exactly
+    Literal(String),
+    /// `*` - matches exactly one word
+    Star,
+    /// `#` - matches zero or more words
+    Hash,
+}
+
+impl PatternWord {
+    /// The word as it appears in a binding key
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Literal(word) => word,
+            Self::Star => "*",
+            Self::Hash => "#",
+        }
+    }
+}
+
+/// A general binding key, eg `#.error`, `portland.#` or `*.*.error`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BindingPattern {
+    words: Vec<PatternWord>,
+}
+
+impl BindingPattern {
+    /// Build a pattern from its words, validating the length of the key
+    pub fn new(words: Vec<PatternWord>) -> Result<Self, AnyhowError> {
+        let pattern = Self::from_words(words);
+        let len = pattern.to_string().len();
+        if len > MAX_KEY_BYTES {
+            return Err(anyhow!("binding key is {} bytes long. It may not exceed {} bytes", len, MAX_KEY_BYTES));
+        }
+        Ok(pattern)
+    }
+
+    // build a pattern from its words as they are, leaving the length of the
+    // key unchecked
+    pub(crate) fn from_words(words: Vec<PatternWord>) -> Self {
+        Self { words }
+    }
+
+    /// The words making up the pattern
+    pub fn words(&self) -> &[PatternWord] {
+        &self.words
+    }
+
+    /// Does the pattern contain a `*` or `#`?
+    pub fn has_wildcards(&self) -> bool {
+        self.words.iter().any(|word| !matches!(word, PatternWord::Literal(_)))
+    }
+
+    /// Would a message published with the supplied RoutingKey be delivered to a
+    /// queue bound with this pattern?
+    pub fn matches(&self, key: &RoutingKey) -> bool {
+        self.matches_str(&key.to_string())
+    }
+
+    /// Would a message published with the supplied raw routing key be delivered
+    /// to a queue bound with this pattern?
+    pub fn matches_str(&self, routing_key: &str) -> bool {
+        let pattern = self.words.iter().map(PatternWord::as_str).collect::<Vec<_>>();
+        words_match(&pattern, &words(routing_key))
+    }
+}
+
+impl FromStr for BindingPattern {
+    type Err = AnyhowError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Self::new(Vec::new());
+        }
+        let words = s.split('.').map(|word| match word {
+            "*" => Ok(PatternWord::Star),
+            "#" => Ok(PatternWord::Hash),
+            word if word.contains('*') || word.contains('#') => {
+                Err(anyhow!("malformed binding key: {}. '*' and '#' must make up a whole word", s))
+            }
+            word => Ok(PatternWord::Literal(word.to_string())),
+        }).collect::<Result<Vec<_>, _>>()?;
+        Self::new(words)
+    }
+}
+
+impl fmt::Display for BindingPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words = self.words.iter().map(PatternWord::as_str).collect::<Vec<_>>();
+        write!(f, "{}", words.join("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_given_wildcards_parses_words() {
+        let pattern = BindingPattern::from_str("*.#.error").unwrap();
+        assert_eq!(pattern.words(), &[
+            PatternWord::Star,
+            PatternWord::Hash,
+            PatternWord::Literal("error".to_string()),
+        ]);
+        assert!(pattern.has_wildcards());
+        assert_eq!(pattern.to_string(), "*.#.error");
+    }
+
+    #[test]
+    fn from_str_given_partial_wildcard_fails() {
+        assert!(BindingPattern::from_str("port*.error").is_err());
+        assert!(BindingPattern::from_str("portland.err#").is_err());
+    }
+
+    #[test]
+    fn from_str_enforces_length_limit() {
+        let longest = vec!["a"; 128].join(".");
+        assert_eq!(longest.len(), MAX_KEY_BYTES);
+        assert!(BindingPattern::from_str(&longest).is_ok());
+        assert!(BindingPattern::from_str(&format!("{}.a", longest)).is_err());
+    }
+
+    #[test]
+    fn matches_str_follows_topic_rules() {
+        let pattern = BindingPattern::from_str("#.error").unwrap();
+        assert!(pattern.matches_str("error"));
+        assert!(pattern.matches_str("portland.error"));
+        assert!(!pattern.matches_str("portland.warn"));
+        let pattern = BindingPattern::from_str("*.*.error").unwrap();
+        assert!(pattern.matches_str("us.portland.error"));
+        assert!(!pattern.matches_str("portland.error"));
+    }
+}
@@ -2,22 +2,23 @@ use anyhow::anyhow;
 use anyhow::Result as AnyhowResult;
 use lapin::{
     options::*, publisher_confirm::Confirmation, types::FieldTable, 
-    BasicProperties, Connection, 
+    BasicProperties, 
     ConnectionProperties, Result, 
 };
 use std::env;
 use std::str::FromStr;
 use tracing::{info,error};
 
-use topic::{LOCALHOST, EXCHANGE, EXCHANGE_TYPE, RoutingKey};
+use topic::{location, EXCHANGE, EXCHANGE_TYPE, RoutingKey};
+use rabbit_common::{logging, LogLevel};
 
 // set up default logging level and initialize tracing
 fn setup() {
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info");
+    logging::init(LogLevel::Info);
+    if let Err(err) = location::init_from_env() {
+        error!("Unable to load locations: {}", err);
+        std::process::exit(1);
     }
-    
-    tracing_subscriber::fmt::init();
 }
 
 fn _parse_args() -> AnyhowResult<(RoutingKey, String)> {
@@ -27,14 +28,15 @@ fn _parse_args() -> AnyhowResult<(RoutingKey, String)> {
     }
 
     let routing_key = RoutingKey::from_str(&args[1])
-                                    .map_err(|_| anyhow!("unable to convert {} to RoutingKey",&args[1]))?;
+                                    .map_err(|e| anyhow!("unable to convert {} to RoutingKey: {}",&args[1], e))?;
     
     // if !routing_key.is_specific() {
     //     error!("invaild routing key: {:?}", routing_key);
     //     std::process::exit(1);
     // }
 
-    let msg = args[1..args.len()].join(" ");
+    // the routing key travels with the message, so need not be part of it
+    let msg = args[2..args.len()].join(" ");
 
     Ok((routing_key, msg))
 }
@@ -53,9 +55,9 @@ async fn main() -> Result<()> {
     let (routing_key,msg) = parse_args();
     
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
   
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -65,13 +67,13 @@ async fn main() -> Result<()> {
     let channel_a = conn.create_channel().await?;
     info!("created channel");
 
-    let exchange = channel_a.exchange_declare(
+    channel_a.exchange_declare(
         EXCHANGE,
         EXCHANGE_TYPE,
         ExchangeDeclareOptions::default(),
         FieldTable::default()
     ).await?;
-    info!(?exchange, "Declared exchange: {}", &EXCHANGE);
+    info!("Declared exchange: {}", EXCHANGE);
     info!("emitting '{}' exchange: {} routing_key: {}", &msg, &EXCHANGE, &routing_key);
     let confirm = channel_a
         .basic_publish(
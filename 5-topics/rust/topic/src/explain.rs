@@ -0,0 +1,278 @@
+//! explain
+//!
+//! # Explain
+//! Work out how the topic exchange routes messages to a set of subscribers,
+//! each bound with its own binding keys (as passed to `receive-logs -k`),
+//! without asking the broker: which subscribers receive a given routing key
+//! (`explain`), and, across every routing key, which bindings overlap, which are
+//! redundant, and which never match (`audit`).
+//!
+//! Every routing key is covered by checking a sample of them: each location and
+//! level, without a service, and with each word the bindings name as well as one
+//! they do not (which stands for every other service).
+use anyhow::anyhow;
+use anyhow::Error as AnyhowError;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use crate::{BindingKey, LocationRegistry, LogLevel, PatternWord, RoutingKey};
+
+const LEVELS: [LogLevel; 4] = [LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error];
+
+/// A queue, along with the binding keys it is bound with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscriber {
+    pub name: String,
+    pub keys: Vec<BindingKey>,
+}
+
+impl FromStr for Subscriber {
+    type Err = AnyhowError;
+
+    /// Parse `<name>=<key>[,<key>...]`, eg `alerts=*.warn,*.error`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pieces = s.splitn(2, '=');
+        let name = pieces.next().unwrap_or_default().trim();
+        let keys = pieces.next()
+            .ok_or_else(|| anyhow!("cannot convert {} to Subscriber. Input should be <name>=<key>[,<key>...]", s))?;
+        if name.is_empty() {
+            return Err(anyhow!("subscriber in {} has no name", s));
+        }
+        let keys = keys.split(',')
+            .map(|key| match key.trim() {
+                "" => Err(anyhow!("subscriber {} has an empty binding key", name)),
+                key => BindingKey::from_str(key),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self{name: name.to_string(), keys})
+    }
+}
+
+/// One of a subscriber's bindings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub subscriber: String,
+    pub key: BindingKey,
+}
+
+/// A subscriber which receives a routing key, along with the bindings which
+/// match it. The queue receives a single copy, however many bindings match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub subscriber: String,
+    pub via: Vec<BindingKey>,
+}
+
+/// Which subscribers receive a message published with the supplied routing key?
+pub fn explain(subscribers: &[Subscriber], key: &RoutingKey) -> Vec<Delivery> {
+    subscribers.iter()
+        .filter_map(|subscriber| {
+            let via = subscriber.keys.iter()
+                .filter(|binding| binding.matches(key))
+                .cloned()
+                .collect::<Vec<_>>();
+            if via.is_empty() {
+                None
+            } else {
+                Some(Delivery{subscriber: subscriber.name.clone(), via})
+            }
+        })
+        .collect()
+}
+
+/// A binding which receives nothing that another binding of the same
+/// subscriber does not (eg `portland.*` alongside `#`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redundant {
+    pub binding: Binding,
+    pub covered_by: BindingKey,
+}
+
+/// Bindings of two subscribers which both receive some routing keys, such as
+/// `example`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    pub first: Binding,
+    pub second: Binding,
+    pub example: RoutingKey,
+}
+
+/// The findings of `audit`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Audit {
+    /// Bindings made redundant by another of the same subscriber
+    pub redundant: Vec<Redundant>,
+    /// Bindings of different subscribers which match the same routing keys,
+    /// leaving out those which are redundant
+    pub overlaps: Vec<Overlap>,
+    /// Bindings which match no routing key at all
+    pub unreachable: Vec<Binding>,
+    /// Routing keys which no subscriber receives
+    pub unreceived: Vec<RoutingKey>,
+    /// The service standing in for those not named by any binding
+    pub other_service: String,
+}
+
+/// Check the bindings of the supplied subscribers against every routing key
+/// made up of the locations in the registry
+pub fn audit(subscribers: &[Subscriber], registry: &LocationRegistry) -> Audit {
+    let (samples, other_service) = sample_keys(subscribers, registry);
+    let bindings = subscribers.iter()
+        .flat_map(|subscriber| subscriber.keys.iter().map(move |key| Binding{subscriber: subscriber.name.clone(), key: key.clone()}))
+        .collect::<Vec<_>>();
+    // the samples matched by each binding
+    let matched = bindings.iter()
+        .map(|binding| samples.iter().enumerate()
+            .filter(|(_, key)| binding.key.matches(key))
+            .map(|(idx, _)| idx)
+            .collect::<BTreeSet<_>>())
+        .collect::<Vec<_>>();
+
+    let mut redundant = Vec::new();
+    let mut overlaps = Vec::new();
+    let mut unreachable = Vec::new();
+    // the bindings which are neither unreachable nor redundant
+    let mut reported = Vec::new();
+    for (i, binding) in bindings.iter().enumerate() {
+        if matched[i].is_empty() {
+            unreachable.push(binding.clone());
+            continue;
+        }
+        // of two equivalent bindings, the later one is redundant
+        let covering = (0..bindings.len()).find(|&j| {
+            j != i
+                && bindings[j].subscriber == binding.subscriber
+                && matched[i].is_subset(&matched[j])
+                && (j < i || matched[i] != matched[j])
+        });
+        if let Some(j) = covering {
+            redundant.push(Redundant{binding: binding.clone(), covered_by: bindings[j].key.clone()});
+            continue;
+        }
+        reported.push(i);
+    }
+    // the overlaps of redundant bindings are those of the bindings covering them
+    for (n, &i) in reported.iter().enumerate() {
+        for &j in &reported[n + 1..] {
+            if bindings[j].subscriber == bindings[i].subscriber {
+                continue;
+            }
+            if let Some(&idx) = matched[i].intersection(&matched[j]).next() {
+                overlaps.push(Overlap{first: bindings[i].clone(), second: bindings[j].clone(), example: samples[idx].clone()});
+            }
+        }
+    }
+
+    let unreceived = samples.iter().enumerate()
+        .filter(|(idx, _)| !matched.iter().any(|keys| keys.contains(idx)))
+        .map(|(_, key)| key.clone())
+        .collect();
+    Audit{redundant, overlaps, unreachable, unreceived, other_service}
+}
+
+// a routing key for each location and level, without a service, and with each
+// word named by the bindings, as well as one which is not
+fn sample_keys(subscribers: &[Subscriber], registry: &LocationRegistry) -> (Vec<RoutingKey>, String) {
+    let named = subscribers.iter()
+        .flat_map(|subscriber| subscriber.keys.iter())
+        .flat_map(|key| key.to_pattern().words().to_vec())
+        .filter_map(|word| match word {
+            PatternWord::Literal(word) => Some(word),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    let other_service = (0..)
+        .map(|n| if n == 0 { "other".to_string() } else { format!("other{}", n) })
+        .find(|service| !named.contains(service))
+        .expect("an unused service");
+
+    let mut samples = Vec::new();
+    for location in registry.locations() {
+        for &level in &LEVELS {
+            let key = RoutingKey::new(location.clone(), level);
+            for service in named.iter().chain(Some(&other_service)) {
+                // skip words which may not be services, or make the key too long
+                if let Ok(key) = key.clone().with_service(service.as_str()) {
+                    samples.push(key);
+                }
+            }
+            samples.push(key);
+        }
+    }
+    (samples, other_service)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscribers(specs: &[&str]) -> Vec<Subscriber> {
+        specs.iter().map(|spec| Subscriber::from_str(spec).unwrap()).collect()
+    }
+
+    fn names(deliveries: &[Delivery]) -> Vec<&str> {
+        deliveries.iter().map(|delivery| delivery.subscriber.as_str()).collect()
+    }
+
+    #[test]
+    fn subscriber_from_str_parses_keys() {
+        let subscriber = Subscriber::from_str("alerts=*.warn, bc.err").unwrap();
+        assert_eq!(subscriber.name, "alerts");
+        assert_eq!(subscriber.keys.iter().map(BindingKey::to_string).collect::<Vec<_>>(), ["*.warn", "vancouver.error"]);
+        for input in &["alerts", "=*.warn", "alerts=", "alerts=nowhere.*"] {
+            assert!(Subscriber::from_str(input).is_err(), "{}", input);
+        }
+    }
+
+    // the receivers from the README
+    #[test]
+    fn explain_lists_subscribers_and_bindings() {
+        let subscribers = subscribers(&["alerts=*.warn,*.error", "portland=portland.*", "everything=#"]);
+        let deliveries = explain(&subscribers, &RoutingKey::from_str("portland.warn").unwrap());
+        assert_eq!(names(&deliveries), ["alerts", "portland", "everything"]);
+        assert_eq!(deliveries[0].via, [BindingKey::from_str("*.warn").unwrap()]);
+
+        let deliveries = explain(&subscribers, &RoutingKey::from_str("mt.render.warn").unwrap());
+        assert_eq!(names(&deliveries), ["everything"]);
+    }
+
+    #[test]
+    fn audit_finds_redundant_bindings() {
+        let subscribers = subscribers(&["a=portland.*,#,pd.#", "b=*.warn,*.warning"]);
+        let audit = audit(&subscribers, &LocationRegistry::builtin());
+        let redundant = audit.redundant.iter()
+            .map(|r| (r.binding.subscriber.as_str(), r.binding.key.to_string(), r.covered_by.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(redundant, [
+            ("a", "portland.*".to_string(), "#".to_string()),
+            ("a", "portland.#".to_string(), "#".to_string()),
+            ("b", "*.warn".to_string(), "*.warn".to_string()),
+        ]);
+        assert!(audit.unreceived.is_empty());
+    }
+
+    #[test]
+    fn audit_finds_overlaps_between_subscribers() {
+        let subscribers = subscribers(&["alerts=*.warn", "portland=portland.*,pd.warn", "render=*.render.*"]);
+        let audit = audit(&subscribers, &LocationRegistry::builtin());
+        assert_eq!(audit.overlaps.len(), 1);
+        let overlap = &audit.overlaps[0];
+        assert_eq!((overlap.first.subscriber.as_str(), overlap.second.subscriber.as_str()), ("alerts", "portland"));
+        assert_eq!(overlap.example.to_string(), "portland.warn");
+    }
+
+    #[test]
+    fn audit_finds_unreachable_bindings_and_unreceived_keys() {
+        let subscribers = subscribers(&["a=*.*.*.*,portland.#.nope,*.render.*", "b=#.error"]);
+        let audit = audit(&subscribers, &LocationRegistry::builtin());
+        let unreachable = audit.unreachable.iter().map(|b| b.key.to_string()).collect::<Vec<_>>();
+        assert_eq!(unreachable, ["*.*.*.*", "portland.#.nope"]);
+
+        let unreceived = audit.unreceived.iter().map(RoutingKey::to_string).collect::<BTreeSet<_>>();
+        assert_eq!(audit.other_service, "other");
+        assert!(unreceived.contains("portland.warn"));
+        assert!(unreceived.contains("portland.other.warn"));
+        assert!(!unreceived.contains("portland.render.warn"));
+        assert!(!unreceived.contains("portland.other.error"));
+    }
+}
@@ -0,0 +1,83 @@
+//! topic-explain
+//!
+//! Explain how the topic exchange routes messages to a set of subscribers,
+//! without connecting to RabbitMq. Given a routing key, show which subscribers
+//! receive it. Otherwise, audit the subscribers' bindings.
+use structopt::StructOpt;
+use topic::explain::{self, Audit, Subscriber};
+use topic::{location, RoutingKey};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name="topic-explain", about="explains which subscribers receive which routing keys")]
+struct Opt {
+    /// A subscriber, and the keys it passes to receive-logs, as
+    /// <name>=<key>[,<key>...] (eg `alerts=*.warn,*.error`). May be repeated
+    #[structopt(short="s", long="subscriber", required=true, number_of_values=1)]
+    subscribers: Vec<Subscriber>,
+    /// Show the subscribers which receive this routing key, rather than
+    /// auditing the bindings
+    key: Option<RoutingKey>,
+}
+
+fn main() {
+    // the locations must be known before the keys are parsed
+    if let Err(err) = location::init_from_env() {
+        eprintln!("Unable to load locations: {}", err);
+        std::process::exit(1);
+    }
+    let opt = Opt::from_args();
+    match opt.key {
+        Some(key) => print_explanation(&opt.subscribers, &key),
+        None => print_audit(&explain::audit(&opt.subscribers, location::registry())),
+    }
+}
+
+fn print_explanation(subscribers: &[Subscriber], key: &RoutingKey) {
+    let deliveries = explain::explain(subscribers, key);
+    if deliveries.is_empty() {
+        println!("{} is not received by any subscriber", key);
+        return;
+    }
+    println!("{} is received by:", key);
+    for delivery in deliveries {
+        let via = delivery.via.iter().map(ToString::to_string).collect::<Vec<_>>();
+        println!("  {} (via {})", delivery.subscriber, via.join(", "));
+    }
+}
+
+fn print_audit(audit: &Audit) {
+    println!("Redundant bindings:");
+    for redundant in &audit.redundant {
+        println!("  {}: {} is covered by {}", redundant.binding.subscriber, redundant.binding.key, redundant.covered_by);
+    }
+    if audit.redundant.is_empty() {
+        println!("  none");
+    }
+
+    println!("Overlapping bindings:");
+    for overlap in &audit.overlaps {
+        println!(
+            "  {}: {} and {}: {} both receive eg {}",
+            overlap.first.subscriber, overlap.first.key, overlap.second.subscriber, overlap.second.key, overlap.example
+        );
+    }
+    if audit.overlaps.is_empty() {
+        println!("  none");
+    }
+
+    println!("Bindings which never match:");
+    for binding in &audit.unreachable {
+        println!("  {}: {}", binding.subscriber, binding.key);
+    }
+    if audit.unreachable.is_empty() {
+        println!("  none");
+    }
+
+    println!("Routing keys which nobody receives ({} stands for any service not named by a binding):", audit.other_service);
+    for key in &audit.unreceived {
+        println!("  {}", key);
+    }
+    if audit.unreceived.is_empty() {
+        println!("  none");
+    }
+}
@@ -4,36 +4,24 @@
 use lapin::ExchangeKind;
 
 
-pub mod loglevel;
-pub use loglevel::LogLevel;
+pub use rabbit_common::LogLevel;
 pub mod location;
-pub use location::Location;
+pub use location::{Location, LocationRegistry};
 pub mod routing_key;
 pub use routing_key::RoutingKey;
 
 pub mod binding_key;
 pub use binding_key::BindingKey;
+pub mod binding_pattern;
+pub use binding_pattern::{BindingPattern, PatternWord};
 
-pub const LOCALHOST: &'static str = "amqp://127.0.0.1:5672/%2f";
-pub const EXCHANGE: &'static str = "routed_logs_rust";
+pub mod matching;
+pub use matching::topic_matches;
+pub mod explain;
+
+/// The longest routing or binding key permitted, in bytes
+pub const MAX_KEY_BYTES: usize = 255;
+pub const EXCHANGE: &str = "routed_logs_rust";
 pub const EXCHANGE_TYPE: ExchangeKind = ExchangeKind::Topic;
 // we are going to create the queue and delete it when finished. (declaring it exlusive and supplying a blank name)
-pub const QUEUE: &'static str = "";
-
-pub mod quit_service {
-    use std::io;
-    pub fn prompt() {
-        println!("Type q or exit to quit");
-        loop {
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-               Ok(_goes_into_input_above) => {},
-               Err(_no_updates_is_fine) => {},
-            }
-            let input = input.trim().to_string();
-            if input == "q" || input == "quit" || input == "exit" {
-               return 
-           }
-       }
-    }
-}
\ No newline at end of file
+pub const QUEUE: &str = "";
@@ -1,14 +1,315 @@
-use strum::{EnumString, AsRefStr};
-
-/// A studio location
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, EnumString, AsRefStr)]
-pub enum Location {
-    #[strum(serialize="playa", serialize="dd")]
-    Playa,
-    #[strum(serialize="vancouver", serialize="bc")]
-    Vancouver,
-    #[strum(serialize="portland", serialize="pd")]
-    Portland,
-    #[strum(serialize="montreal", serialize="mt")]
-    Montreal
+//! location
+//!
+//! # Location
+//! The studio locations which may appear in routing keys. Rather than being
+//! hard coded, they are held in a `LocationRegistry`, which may be read from a
+//! TOML file at startup (see `init_from_env`). A location may be nested within
+//! another by naming it with dots (eg `vancouver.stage1` within `vancouver`),
+//! in which case the outer location must be registered too:
+//!
+//! ```toml
+//! [[locations]]
+//! name = "vancouver"
+//! aliases = ["bc"]
+//!
+//! [[locations]]
+//! name = "vancouver.stage1"
+//! aliases = ["bc1"]
+//! ```
+//!
+//! Until a registry is installed, the locations are playa (`dd`), vancouver
+//! (`bc`), portland (`pd`) and montreal (`mt`).
+use anyhow::anyhow;
+use anyhow::Error as AnyhowError;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::MAX_KEY_BYTES;
+
+/// Environment variable naming the TOML file which lists the locations
+pub const ENV_LOCATIONS: &str = "TOPIC_LOCATIONS";
+
+static REGISTRY: OnceCell<LocationRegistry> = OnceCell::new();
+
+/// A studio location, as registered in a LocationRegistry
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    name: String,
+}
+
+impl Location {
+    /// The canonical name of the location, eg `vancouver.stage1`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The location this one is nested within, if any
+    pub fn parent(&self) -> Option<Location> {
+        self.name.rfind('.').map(|idx| Location { name: self.name[..idx].to_string() })
+    }
+
+    /// Is this the supplied location, or one nested within it?
+    pub fn is_within(&self, other: &Location) -> bool {
+        self.name == other.name
+            || (self.name.starts_with(&other.name) && self.name[other.name.len()..].starts_with('.'))
+    }
+}
+
+impl AsRef<str> for Location {
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl FromStr for Location {
+    type Err = AnyhowError;
+
+    /// Look the name (or alias) up in the installed registry
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        registry().parse(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    location: Location,
+    aliases: Vec<String>,
+}
+
+// the layout of the locations file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocationsFile {
+    locations: Vec<LocationConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocationConfig {
+    name: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+}
+
+/// The known locations, along with their aliases
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LocationRegistry {
+    entries: Vec<Entry>,
+}
+
+impl LocationRegistry {
+    /// A registry without any locations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The locations used until another registry is installed
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for (name, alias) in &[("playa", "dd"), ("vancouver", "bc"), ("portland", "pd"), ("montreal", "mt")] {
+            registry.add(name, &[alias]).expect("valid builtin location");
+        }
+        registry
+    }
+
+    /// Register a location, along with its aliases. A nested location (eg
+    /// `vancouver.stage1`) requires the location it is nested within to be
+    /// registered first. Aliases are single words.
+    pub fn add(&mut self, name: &str, aliases: &[&str]) -> Result<Location, AnyhowError> {
+        if name.len() > MAX_KEY_BYTES {
+            return Err(anyhow!("location {} is {} bytes long. It may not exceed {} bytes", name, name.len(), MAX_KEY_BYTES));
+        }
+        if !name.split('.').all(is_word) {
+            return Err(anyhow!("invalid location: '{}'. Its words may not be empty, '*' or '#'", name));
+        }
+        if self.get(name).is_some() {
+            return Err(anyhow!("location {} is already registered", name));
+        }
+        let location = Location { name: name.to_string() };
+        if let Some(parent) = location.parent() {
+            if !self.locations().any(|known| *known == parent) {
+                return Err(anyhow!("location {} is nested within {}, which is not registered", name, parent));
+            }
+        }
+        for (idx, alias) in aliases.iter().enumerate() {
+            if !is_word(alias) || alias.contains('.') {
+                return Err(anyhow!("invalid alias for {}: '{}'. It must be a single word, without '*' or '#'", name, alias));
+            }
+            if *alias == name || self.get(alias).is_some() || aliases[..idx].contains(alias) {
+                return Err(anyhow!("alias {} for {} is already registered", alias, name));
+            }
+        }
+        self.entries.push(Entry {
+            location: location.clone(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+        });
+        Ok(location)
+    }
+
+    /// Parse a registry from TOML, holding a `locations` array of tables, each
+    /// with a `name` and optional `aliases`
+    pub fn from_toml_str(toml: &str) -> Result<Self, AnyhowError> {
+        let file: LocationsFile = toml::from_str(toml)
+            .map_err(|e| anyhow!("unable to parse locations: {}", e))?;
+        if file.locations.is_empty() {
+            return Err(anyhow!("no locations supplied"));
+        }
+        // register outer locations before those nested within them
+        let mut configs = file.locations;
+        configs.sort_by_key(|config| config.name.matches('.').count());
+        let mut registry = Self::new();
+        for config in &configs {
+            let aliases = config.aliases.iter().map(String::as_str).collect::<Vec<_>>();
+            registry.add(&config.name, &aliases)?;
+        }
+        Ok(registry)
+    }
+
+    /// Read a registry from the TOML file at the supplied path
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AnyhowError> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path)
+            .map_err(|e| anyhow!("unable to read {}: {}", path.display(), e))?;
+        Self::from_toml_str(&toml)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// Look up a location by its canonical name or an alias
+    pub fn get(&self, name: &str) -> Option<&Location> {
+        self.entries.iter()
+            .find(|entry| entry.location.name == name || entry.aliases.iter().any(|alias| alias == name))
+            .map(|entry| &entry.location)
+    }
+
+    /// Look up a location by its canonical name or an alias, failing with an
+    /// error which lists the known locations
+    pub fn parse(&self, name: &str) -> Result<Location, AnyhowError> {
+        self.get(name).cloned().ok_or_else(|| self.unknown(name))
+    }
+
+    /// The registered locations, in the order they were added
+    pub fn locations(&self) -> impl Iterator<Item = &Location> {
+        self.entries.iter().map(|entry| &entry.location)
+    }
+
+    /// Make this the registry used when parsing locations, routing keys and
+    /// binding keys. This may only be done once, and before any of them are
+    /// parsed.
+    pub fn install(self) -> Result<&'static LocationRegistry, AnyhowError> {
+        REGISTRY.set(self)
+            .map_err(|_| anyhow!("the location registry has already been installed"))?;
+        Ok(registry())
+    }
+
+    // the locations named by the leading words of a key, along with the number
+    // of words each spans, longest first
+    pub(crate) fn prefixes(&self, words: &[&str]) -> Vec<(Location, usize)> {
+        (1..=words.len()).rev()
+            .filter_map(|len| self.get(&words[..len].join(".")).map(|location| (location.clone(), len)))
+            .collect()
+    }
+
+    pub(crate) fn unknown(&self, name: &str) -> AnyhowError {
+        let known = self.entries.iter()
+            .map(|entry| if entry.aliases.is_empty() {
+                entry.location.name.clone()
+            } else {
+                format!("{} ({})", entry.location.name, entry.aliases.join(", "))
+            })
+            .collect::<Vec<_>>();
+        anyhow!("unknown location: '{}'. Known locations: {}", name, known.join(", "))
+    }
+}
+
+fn is_word(word: &str) -> bool {
+    !word.is_empty() && !word.contains(&['*', '#'][..])
+}
+
+/// The registry used when parsing locations, routing keys and binding keys:
+/// the one installed, or else the builtin locations
+pub fn registry() -> &'static LocationRegistry {
+    REGISTRY.get_or_init(LocationRegistry::builtin)
+}
+
+/// Install the locations listed in the file named by `TOPIC_LOCATIONS`, if it
+/// is set, leaving the builtin locations otherwise
+pub fn init_from_env() -> Result<&'static LocationRegistry, AnyhowError> {
+    match env::var_os(ENV_LOCATIONS) {
+        Some(path) => LocationRegistry::from_file(path)?.install(),
+        None => Ok(registry()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STUDIOS: &str = r#"
+        [[locations]]
+        name = "vancouver.stage1"
+        aliases = ["bc1"]
+
+        [[locations]]
+        name = "vancouver"
+        aliases = ["bc"]
+
+        [[locations]]
+        name = "london"
+    "#;
+
+    #[test]
+    fn from_toml_str_given_hierarchy_registers_parents_first() {
+        let registry = LocationRegistry::from_toml_str(STUDIOS).unwrap();
+        let names = registry.locations().map(Location::name).collect::<Vec<_>>();
+        assert_eq!(names, ["vancouver", "london", "vancouver.stage1"]);
+        let stage1 = registry.parse("bc1").unwrap();
+        assert_eq!(stage1.name(), "vancouver.stage1");
+        assert_eq!(stage1.parent().as_ref(), registry.get("bc"));
+        assert!(stage1.is_within(registry.get("vancouver").unwrap()));
+        assert!(!registry.get("vancouver").unwrap().is_within(&stage1));
+    }
+
+    #[test]
+    fn from_toml_str_given_invalid_locations_fails() {
+        for toml in &[
+            "",
+            "locations = []",
+            "[[locations]]\nname = \"vancouver.stage1\"",
+            "[[locations]]\nname = \"van*\"",
+            "[[locations]]\nname = \"vancouver\"\naliases = [\"bc.1\"]",
+            "[[locations]]\nname = \"vancouver\"\n[[locations]]\nname = \"portland\"\naliases = [\"vancouver\"]",
+            "[[locations]]\nname = \"vancouver\"\ncity = \"vancouver\"",
+        ] {
+            assert!(LocationRegistry::from_toml_str(toml).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn parse_given_unknown_location_lists_known_locations() {
+        let err = LocationRegistry::builtin().parse("nowhere").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown location: 'nowhere'. Known locations: playa (dd), vancouver (bc), portland (pd), montreal (mt)"
+        );
+    }
+
+    #[test]
+    fn prefixes_are_longest_first() {
+        let registry = LocationRegistry::from_toml_str(STUDIOS).unwrap();
+        let prefixes = registry.prefixes(&["vancouver", "stage1", "error"]);
+        let found = prefixes.iter().map(|(location, len)| (location.name(), *len)).collect::<Vec<_>>();
+        assert_eq!(found, [("vancouver.stage1", 2), ("vancouver", 1)]);
+        assert!(registry.prefixes(&["stage1", "error"]).is_empty());
+    }
 }
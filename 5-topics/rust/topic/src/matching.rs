@@ -0,0 +1,115 @@
+//! matching
+//!
+//! # Matching
+//! Decide whether a message published with a given routing key would be
+//! delivered to a queue bound with a given binding key, following the rules of
+//! the AMQP `topic` exchange, without asking the broker:
+//! - keys are lists of words delimited by periods
+//! - `*` (star) in the binding key matches exactly one word
+//! - `#` (hash) in the binding key matches zero or more words
+//! - any other word must match exactly
+
+/// Would a message published with the supplied routing key be delivered to a
+/// queue bound with the supplied binding key? Both are given as raw, dot
+/// delimited strings (eg `"*.warn"` and `"portland.warn"`).
+pub fn topic_matches(binding_key: &str, routing_key: &str) -> bool {
+    words_match(&words(binding_key), &words(routing_key))
+}
+
+// does the pattern (binding key) match the key (routing key), each given as
+// its words
+pub(crate) fn words_match(pattern: &[&str], key: &[&str]) -> bool {
+    // matched[j] is true when the binding words seen so far match the first j
+    // words of the routing key
+    let mut matched = vec![false; key.len() + 1];
+    matched[0] = true;
+    for &word in pattern {
+        let mut next = vec![false; key.len() + 1];
+        for j in 0..=key.len() {
+            next[j] = match word {
+                // zero words, or one more word than a shorter match
+                "#" => matched[j] || (j > 0 && next[j - 1]),
+                "*" => j > 0 && matched[j - 1],
+                literal => j > 0 && matched[j - 1] && key[j - 1] == literal,
+            };
+        }
+        matched = next;
+    }
+    matched[key.len()]
+}
+
+// an empty key holds no words at all, rather than one empty word
+pub(crate) fn words(key: &str) -> Vec<&str> {
+    if key.is_empty() {
+        Vec::new()
+    } else {
+        key.split('.').collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_matches_given_literal_words_requires_equality() {
+        assert!(topic_matches("stock.usd.nyse", "stock.usd.nyse"));
+        assert!(!topic_matches("stock.usd.nyse", "stock.usd.nasdaq"));
+        assert!(!topic_matches("stock.usd", "stock.usd.nyse"));
+        assert!(!topic_matches("stock.usd.nyse", "stock.usd"));
+    }
+
+    #[test]
+    fn topic_matches_given_star_matches_exactly_one_word() {
+        assert!(topic_matches("*.warn", "portland.warn"));
+        assert!(topic_matches("portland.*", "portland.error"));
+        assert!(!topic_matches("*.warn", "warn"));
+        assert!(!topic_matches("*.warn", "us.portland.warn"));
+        assert!(!topic_matches("*", ""));
+    }
+
+    #[test]
+    fn topic_matches_given_hash_matches_zero_or_more_words() {
+        for key in &["", "portland", "portland.warn", "us.portland.warn"] {
+            assert!(topic_matches("#", key), "# should match '{}'", key);
+        }
+        assert!(topic_matches("portland.#", "portland"));
+        assert!(topic_matches("portland.#", "portland.warn.disk"));
+        assert!(topic_matches("#.warn", "warn"));
+        assert!(topic_matches("#.warn.#", "us.portland.warn.disk"));
+        assert!(topic_matches("a.#.z", "a.z"));
+        assert!(topic_matches("a.#.z", "a.b.c.z"));
+        assert!(!topic_matches("a.#.z", "a.b.c"));
+        assert!(topic_matches("#.#", "a.b"));
+        assert!(topic_matches("*.#", "a"));
+        assert!(!topic_matches("*.#", ""));
+    }
+
+    // the examples from the rabbitmq topics tutorial, on which the README is based
+    #[test]
+    fn topic_matches_tutorial_examples() {
+        let bindings = ["*.orange.*", "*.*.rabbit", "lazy.#"];
+        for (key, expected) in &[
+            ("quick.orange.rabbit", [true, true, false]),
+            ("lazy.orange.elephant", [true, false, true]),
+            ("quick.orange.fox", [true, false, false]),
+            ("lazy.brown.fox", [false, false, true]),
+            ("lazy.pink.rabbit", [false, true, true]),
+            ("quick.brown.fox", [false, false, false]),
+            ("orange", [false, false, false]),
+            ("quick.orange.male.rabbit", [false, false, false]),
+            ("lazy.orange.male.rabbit", [false, false, true]),
+        ] {
+            for (binding, expected) in bindings.iter().zip(expected.iter()) {
+                assert_eq!(topic_matches(binding, key), *expected, "{} against {}", binding, key);
+            }
+        }
+    }
+
+    #[test]
+    fn topic_matches_given_no_wildcards_behaves_like_direct() {
+        assert!(topic_matches("portland.warn", "portland.warn"));
+        assert!(!topic_matches("portland.warn", "portland.error"));
+        assert!(!topic_matches("portland.warn", "vancouver.warn"));
+    }
+}
@@ -1,14 +1,14 @@
 
-use async_std;
     use lapin::{
-        options::*,  types::FieldTable,  Connection,
+        options::*,  types::FieldTable,  
         ConnectionProperties, Result, message::DeliveryResult
     };
 use std::time;
 use structopt::StructOpt;
 use tracing::info;
-use topic::{LOCALHOST, QUEUE, EXCHANGE, EXCHANGE_TYPE, BindingKey};
-use topic::quit_service;
+use topic::{location, QUEUE, EXCHANGE, EXCHANGE_TYPE, BindingKey};
+use rabbit_common::{logging, LogLevel};
+use rabbit_common::shutdown::{self, InFlight};
 
 
 #[derive(Debug, StructOpt)]
@@ -20,18 +20,17 @@ struct Opt {
     /// specified by this flag will be processed asynchronously
     #[structopt(short="n", long="num-msgs")]
     num_msgs: Option<u16>,
-    /// Add supported routing keys
+    /// Add supported routing keys: <location>.<level> or
+    /// <location>.<service>.<level>, with `*` and `#` wildcards (eg `*.render.*`)
     #[structopt(short="k", long="key")]
     keys: Vec<BindingKey>
 }
 
 
 fn initialize() {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
-    
-    tracing_subscriber::fmt::init();
+    logging::init(LogLevel::Info);
+    // the locations must be known before the keys are parsed
+    location::init_from_env().expect("unable to load locations");
 }
 #[async_std::main]
 async fn main() -> Result<()> {
@@ -39,10 +38,10 @@ async fn main() -> Result<()> {
     // process args
     let opt = Opt::from_args();
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
+    let addr = rabbit_common::amqp_addr();
     // Establish connection
     info!("Attempting to connect to rabbit at : {}", addr);
-    let conn = Connection::connect(
+    let conn = rabbit_common::connect(
         &addr,
         ConnectionProperties::default(),
     )
@@ -54,17 +53,16 @@ async fn main() -> Result<()> {
     info!("Channel created");
     
     // Create the exchange from the channel
-    let exchange = channel_b.exchange_declare(
+    channel_b.exchange_declare(
         EXCHANGE,
         EXCHANGE_TYPE,
         ExchangeDeclareOptions::default(),
         FieldTable::default()
     ).await?;
-    info!(?exchange, "Declared exchange: {}", &EXCHANGE);
+    info!("Declared exchange: {}", EXCHANGE);
 
     // declare the queue
-    let mut queue_opts = QueueDeclareOptions::default();
-    queue_opts.exclusive = true;
+    let queue_opts = QueueDeclareOptions{exclusive: true, ..Default::default()};
     let queue = channel_b
         .queue_declare(
             QUEUE,
@@ -78,13 +76,13 @@ async fn main() -> Result<()> {
         
         channel_b.queue_bind(
             // queue name
-            &queue.name().as_str(),
+            queue.name().as_str(),
             EXCHANGE,
             &route.to_string(),
             QueueBindOptions::default(),
             FieldTable::default()
         ).await?;
-        info!("Queue '{}' bound to '{}'", &queue.name().as_str(), &route.to_string());
+        info!("Queue '{}' bound to '{}'", queue.name().as_str(), &route.to_string());
     }
     // Update the quality of service options to limit the consumer to
     // a specific number of messages if requested
@@ -97,7 +95,7 @@ async fn main() -> Result<()> {
     // create a consumer
     let  consumer = channel_b
         .basic_consume(
-            &queue.name().as_str(),
+            queue.name().as_str(),
             "my_consumer",
             BasicConsumeOptions::default(),
             FieldTable::default(),
@@ -106,30 +104,44 @@ async fn main() -> Result<()> {
     info!("Channel Consumer created");
     
     // register the delegate (callback) with the consumer
-    consumer.set_delegate(move |delivery: DeliveryResult| async move {
-        let delivery = delivery.expect("error caught in in consumer");
-        if let Some((channel, delivery)) = delivery {
+    // track the deliveries being worked on, so that shutdown can wait for them
+    let in_flight = InFlight::new();
+    consumer.set_delegate({
+        let in_flight = in_flight.clone();
+        move |delivery: DeliveryResult| {
+            let working = in_flight.start();
+            async move {
+                let _working = working;
+                let delivery = delivery.expect("error caught in in consumer");
+                if let Some((channel, delivery)) = delivery {
             
-            let value = std::str::from_utf8(&delivery.data).unwrap();
-            //let pieces = value.split(" ");
-            //let level = BindingKey::from_str(pieces[0]);
-            //let msg = &pieces[1..].join(" ");
+                    let value = std::str::from_utf8(&delivery.data).unwrap();
+                    //let pieces = value.split(" ");
+                    //let level = BindingKey::from_str(pieces[0]);
+                    //let msg = &pieces[1..].join(" ");
             
-            println!("[x] Start:  {}",value);
-            let sleep_duration = value.matches('.').count()-1;
-            if sleep_duration > 0 {
-                async_std::task::sleep(time::Duration::new(sleep_duration as u64,0)).await;
-            }
-            println!("[x] Finish: {}",value);
+                    let key = delivery.routing_key.as_str();
+                    println!("[x] Start:  {}: {}", key, value);
+                    let sleep_duration = value.matches('.').count();
+                    if sleep_duration > 0 {
+                        async_std::task::sleep(time::Duration::new(sleep_duration as u64,0)).await;
+                    }
+                    println!("[x] Finish: {}: {}", key, value);
             
-            channel
-                .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
-                .await
-                .expect("failed to ack");
-        } 
+                    channel
+                        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                        .await
+                        .expect("failed to ack");
+                } 
+            }
+        }
     })?;
-    
-    Ok(quit_service::prompt())
-   
-}
 
+    // wait for SIGINT, SIGTERM or "q", then stop consuming cleanly
+    shutdown::listen()
+        .expect("unable to install signal handler")
+        .wait()
+        .await;
+    info!("shutting down");
+    shutdown::close(&conn, &channel_b, consumer.tag().as_str(), &in_flight).await
+}
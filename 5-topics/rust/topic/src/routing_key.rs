@@ -1,28 +1,93 @@
 //! a valid routing key in rabbit
 use anyhow::anyhow;
 use anyhow::Error as AnyhowError;
-use crate::Location;
+use crate::{Location, LocationRegistry};
+use crate::location::registry;
 use crate::LogLevel;
 use crate::BindingKey;
+use crate::MAX_KEY_BYTES;
 
 use std::fmt;
 use std::str::FromStr;
 use std::convert::TryFrom;
 
 /// RoutingKey represents a valid routing key in our made up
-/// example. 
-#[derive(Debug, PartialEq, Eq)]
+/// example: <location>.<level>, or <location>.<service>.<level> when the
+/// message names the service which sent it (eg `vancouver.render.error`).
+/// The location may span several words (eg `vancouver.stage1.error`).
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RoutingKey {
     pub location: Location,
+    pub service: Option<String>,
     pub level: LogLevel
 }
 
+impl RoutingKey {
+    /// A RoutingKey which does not name a service
+    pub fn new(location: Location, level: LogLevel) -> Self {
+        Self{location, service: None, level}
+    }
+
+    /// Name the service which sent the message
+    pub fn with_service<S: Into<String>>(self, service: S) -> Result<Self, AnyhowError> {
+        let service = service.into();
+        validate_service(&service)?;
+        Self{service: Some(service), ..self}.checked()
+    }
+
+    // routing keys, like binding keys, are limited to MAX_KEY_BYTES
+    fn checked(self) -> Result<Self, AnyhowError> {
+        let len = self.to_string().len();
+        if len > MAX_KEY_BYTES {
+            return Err(anyhow!("routing key is {} bytes long. It may not exceed {} bytes", len, MAX_KEY_BYTES));
+        }
+        Ok(self)
+    }
+
+    /// Parse a RoutingKey, validating its location against the supplied
+    /// registry. Where the words could name either a nested location or a
+    /// location and service (eg `vancouver.stage1.error`), the nested location
+    /// wins.
+    pub fn parse(s: &str, registry: &LocationRegistry) -> Result<Self, AnyhowError> {
+        let pieces = s.split('.').collect::<Vec<_>>();
+        let mut error = None;
+        for (location, len) in registry.prefixes(&pieces) {
+            let key = match pieces[len..] {
+                [level] => LogLevel::from_str(level)
+                    .map_err(|e| anyhow!("{}", e))
+                    .and_then(|level| Self::new(location, level).checked()),
+                [service, level] => LogLevel::from_str(level)
+                    .map_err(|e| anyhow!("{}", e))
+                    .and_then(|level| Self::new(location, level).with_service(service)),
+                _ => Err(anyhow!("cannot convert {} to RoutingKey. Input should be <location>[.<service>].<loglevel>", s)),
+            };
+            match key {
+                Ok(key) => return Ok(key),
+                // report the problem with the longest location
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        Err(error.unwrap_or_else(|| registry.unknown(pieces[0])))
+    }
+}
+
+/// A service is a single, non empty word, which may not be a wildcard
+pub(crate) fn validate_service(service: &str) -> Result<(), AnyhowError> {
+    if service.is_empty() || service.contains(&['.', '*', '#'][..]) {
+        return Err(anyhow!("invalid service: '{}'. It must be a single word, without '*' or '#'", service));
+    }
+    Ok(())
+}
+
 impl TryFrom<BindingKey> for RoutingKey {
     type Error = AnyhowError;
 
     fn try_from(key: BindingKey) -> Result<Self, Self::Error> {
         match key {
-            BindingKey::Pair{location, level} => Ok(Self{location, level}),
+            BindingKey::Pair{location, level} => Ok(Self::new(location, level)),
+            BindingKey::Service{location: Some(location), service: Some(service), level: Some(level)} => {
+                Ok(Self{location, service: Some(service), level})
+            }
             _ => Err(anyhow!("unable to convert {} to RoutingKey instance", &key.to_string()))
         }
     }
@@ -30,19 +95,72 @@ impl TryFrom<BindingKey> for RoutingKey {
 
 impl fmt::Display for RoutingKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}.{}", self.location.as_ref(), self.level.as_ref())
+        match &self.service {
+            Some(service) => write!(f, "{}.{}.{}", self.location.as_ref(), service, self.level.as_ref()),
+            None => write!(f, "{}.{}", self.location.as_ref(), self.level.as_ref()),
+        }
     }
 }
 
 impl FromStr for RoutingKey {
     type Err = AnyhowError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let pieces = s.split(".").collect::<Vec<_>>();
-        if pieces.len() != 2 {
-            return Err(anyhow!("cannot convert {} to RoutingKey. Input should be <location>.<loglevel>", s));
+        Self::parse(s, registry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_given_two_parts_has_no_service() {
+        let key = RoutingKey::from_str("pd.warning").unwrap();
+        assert_eq!(key, RoutingKey::new(Location::from_str("portland").unwrap(), LogLevel::Warn));
+        assert_eq!(key.to_string(), "portland.warn");
+    }
+
+    #[test]
+    fn from_str_given_three_parts_has_service() {
+        let key = RoutingKey::from_str("bc.render.err").unwrap();
+        assert_eq!(key.location.name(), "vancouver");
+        assert_eq!(key.service.as_deref(), Some("render"));
+        assert_eq!(key.level, LogLevel::Error);
+        assert_eq!(key.to_string(), "vancouver.render.error");
+    }
+
+    #[test]
+    fn from_str_given_bad_service_fails() {
+        for input in &["portland..warn", "portland.*.warn", "portland.#.warn", "portland.a.b.warn", "portland", "nowhere.warn"] {
+            assert!(RoutingKey::from_str(input).is_err(), "{}", input);
         }
-        let location = Location::from_str(&pieces[0]).map_err(|e| anyhow!("{}", e))?;
-        let level = LogLevel::from_str(&pieces[1]).map_err(|e| anyhow!("{}",e))?;
-       Ok(RoutingKey{location, level})
+        let service = "s".repeat(MAX_KEY_BYTES);
+        assert!(RoutingKey::from_str(&format!("portland.{}.warn", service)).is_err());
+    }
+
+    #[test]
+    fn parse_given_nested_location_prefers_it() {
+        let registry = LocationRegistry::from_toml_str(
+            "[[locations]]\nname = \"vancouver\"\n[[locations]]\nname = \"vancouver.stage1\"\naliases = [\"bc1\"]"
+        ).unwrap();
+        let key = RoutingKey::parse("vancouver.stage1.error", &registry).unwrap();
+        assert_eq!((key.location.name(), key.service.as_deref()), ("vancouver.stage1", None));
+        let key = RoutingKey::parse("bc1.render.warn", &registry).unwrap();
+        assert_eq!(key.to_string(), "vancouver.stage1.render.warn");
+        let key = RoutingKey::parse("vancouver.render.warn", &registry).unwrap();
+        assert_eq!((key.location.name(), key.service.as_deref()), ("vancouver", Some("render")));
+        let err = RoutingKey::parse("portland.warn", &registry).unwrap_err();
+        assert!(err.to_string().contains("Known locations: vancouver, vancouver.stage1 (bc1)"), "{}", err);
+    }
+
+    #[test]
+    fn parse_given_long_location_checks_length() {
+        let name = "l".repeat(MAX_KEY_BYTES - ".warn".len());
+        let registry = LocationRegistry::from_toml_str(
+            &format!("[[locations]]\nname = \"{}\"\naliases = [\"long\"]", name)
+        ).unwrap();
+        // spelling out the alias lengthens the key
+        assert_eq!(RoutingKey::parse("long.warn", &registry).unwrap().to_string().len(), MAX_KEY_BYTES);
+        assert!(RoutingKey::parse("long.error", &registry).is_err());
     }
 }
@@ -1,41 +1,107 @@
 use anyhow::Error as AnyhowError;
 use anyhow::anyhow;
-use std::env;
-use rpc::{LogLevel,FibClient};
+use async_std::task;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+use rpc::{LogLevel, FibClient, ConnectionConfig, SimpleClient};
+use rabbit_common::logging;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name="quess", about="provide a fib index")]
 struct Opt {
     /// Index of the value in the fibonacci sequence to 
-    /// calculate
-    #[structopt(name = "GUESS")]
-    guess: u32,
+    /// calculate. Several indices may be supplied, as may
+    /// ranges of up to 10000 indices, such as 10..50 or 10..=50
+    #[structopt(name = "GUESS", required = true)]
+    guesses: Vec<Indices>,
+    /// Number of seconds to wait for a reply from the rpc-server
+    /// before giving up
+    #[structopt(short="t", long="timeout", default_value="10")]
+    timeout: u64,
+    /// Read the connection settings from this TOML file, rather
+    /// than from the AMQP_* environment variables
+    #[structopt(short="c", long="config", parse(from_os_str))]
+    config: Option<PathBuf>,
     /// set the log level
     #[structopt(short="l", long="log-level")]
     loglevel: Option<LogLevel>
 }
 
+// the most indices a single range may cover
+const MAX_RANGE: u32 = 10_000;
+
+// One or more indices into the fibonacci sequence, given as a single index,
+// or as a half open (10..50) or closed (10..=50) range
+#[derive(Debug)]
+struct Indices(Vec<u32>);
+
+impl FromStr for Indices {
+    type Err = AnyhowError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = |idx: &str| idx.trim().parse::<u32>()
+            .map_err(|_| anyhow!("'{}' is not a valid index", idx));
+        let (first, last) = if let Some(pos) = value.find("..=") {
+            (parse(&value[..pos])?, parse(&value[pos + 3..])?)
+        } else if let Some(pos) = value.find("..") {
+            let (start, end) = (parse(&value[..pos])?, parse(&value[pos + 2..])?);
+            if end <= start {
+                return Err(anyhow!("the range '{}' is empty", value));
+            }
+            (start, end - 1)
+        } else {
+            let index = parse(value)?;
+            (index, index)
+        };
+        if last < first {
+            return Err(anyhow!("the range '{}' is empty", value));
+        }
+        // each index is a separate request, so a slip such as 0..1000000 is
+        // rejected rather than sent
+        if last - first >= MAX_RANGE {
+            return Err(anyhow!("the range '{}' has more than {} indices", value, MAX_RANGE));
+        }
+        Ok(Indices((first..=last).collect()))
+    }
+}
+
 // parse args, initialize the log level, and start
 // the tracing
 fn setup() -> Opt {
-    let mut args = Opt::from_args();
-    if args.loglevel.is_some() {
-        let level = args.loglevel.take().unwrap();
-        let levelstr = level.as_ref();
-        env::set_var("RUST_LOG", &levelstr);
-    } else if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "warn");
-    }
-    tracing_subscriber::fmt::init();
+    let args = Opt::from_args();
+    logging::init_with_level(args.loglevel, LogLevel::Warn);
     
     args
 }
 
  fn main() -> Result<(),AnyhowError> {
     let opts = setup();
-    let client = FibClient::new().map_err(|e| anyhow!("{}", e))?;
-    let result = client.fib(opts.guess).map_err(|e| anyhow!("{}", e))?;
-    println!("fib({}) = {}",opts.guess, result);
+    let client = match &opts.config {
+        Some(path) => task::block_on(async {
+            let config = ConnectionConfig::from_file(path)?;
+            FibClient::from_client(SimpleClient::connect(config).await?).await
+        }),
+        None => FibClient::new(),
+    };
+    let mut client = client.map_err(|e| anyhow!("{}", e))?;
+    client.set_timeout(Some(Duration::from_secs(opts.timeout)));
+    let guesses = opts.guesses.into_iter().flat_map(|indices| indices.0).collect::<Vec<_>>();
+    // all of the requests share the one connection and reply queue
+    let mut failed = false;
+    for (guess, result) in guesses.iter().zip(client.fib_many_big(&guesses)) {
+        match result {
+            Ok(result) => println!("fib({}) = {}", guess, result),
+            Err(err) => {
+                eprintln!("ERROR: unable to calculate fib({}): {}", guess, err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
     Ok(())
 }
\ No newline at end of file
@@ -1,9 +1,8 @@
-use async_std;
-use lapin::Result as AsyncResult;
-use std::env;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
-use rpc::{ QUEUE, FibRpcServer, LogLevel};
+use rpc::{ QUEUE, ConnectionConfig, RpcServerBuilder, Router, FibHandler, TypedHandler, LogLevel, Result, checked_fib};
+use rabbit_common::{logging, shutdown};
 
 
 #[derive(Debug, StructOpt)]
@@ -15,6 +14,14 @@ struct Opt {
     /// specified by this flag will be processed asynchronously
     #[structopt(short="n", long="num-msgs")]
     num_msgs: Option<u16>,
+    /// Cache the replies to up to this many distinct requests, answering
+    /// repeats without recalculating them
+    #[structopt(long="cache-size")]
+    cache_size: Option<usize>,
+    /// Read the connection settings from this TOML file, rather
+    /// than from the AMQP_* environment variables
+    #[structopt(short="c", long="config", parse(from_os_str))]
+    config: Option<PathBuf>,
     /// set the log level
     #[structopt(short="l", long="log-level")]
     loglevel: Option<LogLevel>
@@ -22,28 +29,44 @@ struct Opt {
 
 // Perform basic setup, including parsing arguments
 fn setup() -> Opt {
-
-    let mut args = Opt::from_args();
-    if args.loglevel.is_some() {
-        let level = args.loglevel.take().unwrap();
-        let levelstr = level.as_ref();
-        env::set_var("RUST_LOG", &levelstr);
-    } else if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "warn");
-    }
-    tracing_subscriber::fmt::init();
+    let args = Opt::from_args();
+    logging::init_with_level(args.loglevel, LogLevel::Warn);
     
     args
 }
 
 
 #[async_std::main]
-async fn main() -> AsyncResult<()> {
+async fn main() -> Result<()> {
     // process args
     let opt = setup();
-    let mut server = FibRpcServer::with_defaults(QUEUE)?; 
-    server.set_message_count(opt.num_msgs);
-    server.serve()?;
+    // requests which do not name a method (eg from FibClient) calculate fib, 
+    // as they always have
+    let router = Router::new()
+        .method("fib", FibHandler)
+        .method("checked_fib", TypedHandler::new(|idx: u32| checked_fib(idx)))
+        .fallback(FibHandler);
+    let mut builder = RpcServerBuilder::with_handler(router).queue_name(QUEUE);
+    if let Some(num_msgs) = opt.num_msgs {
+        builder = builder.prefetch(num_msgs);
+    }
+    if let Some(cache_size) = opt.cache_size {
+        builder = builder.cache_size(cache_size);
+    }
+    if let Some(path) = &opt.config {
+        builder = builder.connection(ConnectionConfig::from_file(path)?);
+    }
+    let server = builder.connect().await?;
+    // SIGINT, SIGTERM or "q" stop the server, once the requests in flight are
+    // answered (see `shutdown::listen`)
+    shutdown::on_signal(server.shutdown_handle())
+        .expect("unable to install signal handler");
+    shutdown::on_quit(server.shutdown_handle());
+    let summary = server.run().await?;
+    println!("[X] {}", summary);
+    if let Some(stats) = server.cache_stats() {
+        println!("[X] {}", stats);
+    }
     Ok(())
 }
 
@@ -0,0 +1,136 @@
+//! cache
+//!
+//! # Cache
+//! A bounded, least recently used cache of replies, which allows the server to
+//! answer repeated requests without calling the handler again.
+use lru::LruCache;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Identifies a request for the purposes of caching. Requests naming the same
+/// method, with the same content type and payload, share a reply.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    method: Option<String>,
+    content_type: Option<String>,
+    data: Vec<u8>,
+}
+
+impl CacheKey {
+    /// Create a new CacheKey given the method, content type, and payload of a request
+    pub fn new(method: Option<&str>, content_type: Option<&str>, data: &[u8]) -> Self {
+        Self {
+            method: method.map(String::from),
+            content_type: content_type.map(String::from),
+            data: data.to_vec(),
+        }
+    }
+}
+
+/// A snapshot of the performance of a ResultCache
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of lookups answered from the cache
+    pub hits: u64,
+    /// Number of lookups which missed the cache
+    pub misses: u64,
+    /// Number of replies currently cached
+    pub len: usize,
+    /// Maximum number of replies cached
+    pub capacity: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cache: {} hits, {} misses, {}/{} entries",
+            self.hits, self.misses, self.len, self.capacity
+        )
+    }
+}
+
+/// Bounded cache of reply payloads, evicting the least recently used once full.
+/// It may be shared between the tasks serving requests.
+#[derive(Debug)]
+pub struct ResultCache {
+    entries: Mutex<LruCache<CacheKey, Vec<u8>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResultCache {
+    /// Create a new ResultCache holding at most `capacity` replies
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Retrieve a copy of the reply cached for the key, if any, recording the
+    /// hit or miss
+    pub fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        let value = self.entries.lock().unwrap().get(key).cloned();
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Cache the reply for the key
+    pub fn put(&self, key: CacheKey, value: Vec<u8>) {
+        self.entries.lock().unwrap().put(key, value);
+    }
+
+    /// Retrieve the current statistics
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: entries.len(),
+            capacity: entries.cap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(data: &str) -> CacheKey {
+        CacheKey::new(Some("fib"), Some("text/plain"), data.as_bytes())
+    }
+
+    #[test]
+    fn get_counts_hits_and_misses() {
+        let cache = ResultCache::new(2);
+        assert_eq!(cache.get(&key("10")), None);
+        cache.put(key("10"), b"55".to_vec());
+        assert_eq!(cache.get(&key("10")), Some(b"55".to_vec()));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len, stats.capacity), (1, 1, 1, 2));
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let cache = ResultCache::new(2);
+        cache.put(key("1"), b"1".to_vec());
+        cache.put(key("2"), b"1".to_vec());
+        cache.get(&key("1"));
+        cache.put(key("3"), b"2".to_vec());
+        assert!(cache.get(&key("2")).is_none());
+        assert!(cache.get(&key("1")).is_some());
+        assert!(cache.get(&key("3")).is_some());
+    }
+
+    #[test]
+    fn keys_differ_by_method_and_content_type() {
+        let cache = ResultCache::new(4);
+        cache.put(key("10"), b"55".to_vec());
+        assert!(cache.get(&CacheKey::new(None, Some("text/plain"), b"10")).is_none());
+        assert!(cache.get(&CacheKey::new(Some("fib"), None, b"10")).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+//! codec
+//!
+//! # Codec
+//! Encodes and decodes the payloads of typed requests and replies. The codec
+//! used for a message is identified by its `content_type` property.
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Error, Result};
+
+/// Content type of messages encoded as json
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+/// Content type of messages encoded with bincode
+pub const BINCODE_CONTENT_TYPE: &str = "application/x-bincode";
+
+/// The available encodings for typed payloads
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Codec {
+    /// Human readable json
+    #[default]
+    Json,
+    /// Compact binary encoding via bincode
+    Bincode,
+}
+
+impl Codec {
+    /// The content type identifying this codec
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => JSON_CONTENT_TYPE,
+            Self::Bincode => BINCODE_CONTENT_TYPE,
+        }
+    }
+
+    /// Look up the codec identified by the supplied content type
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            JSON_CONTENT_TYPE => Some(Self::Json),
+            BINCODE_CONTENT_TYPE => Some(Self::Bincode),
+            _ => None,
+        }
+    }
+
+    /// Encode the supplied value
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| Error::Codec(e.to_string())),
+            Self::Bincode => bincode::serialize(value).map_err(|e| Error::Codec(e.to_string())),
+        }
+    }
+
+    /// Decode a value from the supplied payload
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        match self {
+            Self::Json => serde_json::from_slice(data).map_err(|e| Error::Codec(e.to_string())),
+            Self::Bincode => bincode::deserialize(data).map_err(|e| Error::Codec(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Point {
+        x: i32,
+        label: String,
+    }
+
+    #[test]
+    fn codec_round_trips_value() {
+        let point = Point { x: -3, label: "origin".into() };
+        for codec in &[Codec::Json, Codec::Bincode] {
+            let data = codec.encode(&point).unwrap();
+            let decoded: Point = codec.decode(&data).unwrap();
+            assert_eq!(decoded, point);
+        }
+    }
+
+    #[test]
+    fn codec_given_content_type_converts() {
+        for codec in &[Codec::Json, Codec::Bincode] {
+            assert_eq!(Codec::from_content_type(codec.content_type()), Some(*codec));
+        }
+        assert_eq!(Codec::from_content_type("text/plain"), None);
+    }
+
+    #[test]
+    fn codec_given_bad_payload_fails() {
+        let decoded: Result<Point> = Codec::Json.decode(b"not json");
+        assert!(decoded.is_err());
+    }
+}
@@ -1,3 +1,6 @@
 
-pub const LOCALHOST: &'static str = "amqp://127.0.0.1:5672/%2f";
-pub const QUEUE: &'static str = "rpc_queue_rust";
\ No newline at end of file
+pub use rabbit_common::LOCALHOST;
+pub const QUEUE: &str = "rpc_queue_rust";
+/// The largest fibonacci index the server is willing to calculate. fib(100000)
+/// already has over 20,000 digits.
+pub const MAX_FIB_INDEX: u32 = 100_000;
@@ -0,0 +1,92 @@
+//! error
+//!
+//! # Error
+//! The error type returned by the public api of the rpc crate
+use std::fmt;
+use std::time::Duration;
+
+use crate::ServeSummary;
+
+/// Errors which may be encountered by the rpc client and server
+#[derive(Debug)]
+pub enum Error {
+    /// An error reported by lapin while talking to RabbitMq, such as
+    /// a failure to connect
+    Connection(lapin::Error),
+    /// No reply was received within the supplied duration
+    Timeout(Duration),
+    /// The reply consumer shut down before a reply arrived
+    Disconnected,
+    /// RabbitMq answered a publish with an unexpected confirmation, eg a nack
+    Confirmation(String),
+    /// The payload of a reply could not be interpreted
+    MalformedReply(String),
+    /// The payload of a request could not be interpreted
+    MalformedRequest(String),
+    /// A request arrived without the `reply_to` property
+    MissingReplyTo,
+    /// A message arrived without the `correlation_id` property
+    MissingCorrelationId,
+    /// The server was unable to carry out the request
+    Remote(String),
+    /// The value at the supplied fibonacci index does not fit in a u64
+    Overflow(u32),
+    /// A typed payload could not be encoded or decoded
+    Codec(String),
+    /// The server does not provide the named method
+    MethodNotFound(String),
+    /// The supplied configuration is invalid
+    Config(String),
+    /// The server lost its connection to RabbitMq and was unable to reconnect,
+    /// having handled the summarized requests until then
+    ConnectionLost {
+        summary: ServeSummary,
+        source: Box<Error>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connection(err) => write!(f, "{}", err),
+            Self::Timeout(duration) => write!(
+                f,
+                "timed out after {:?} waiting for a reply. Is the rpc-server running?",
+                duration
+            ),
+            Self::Disconnected => write!(f, "reply consumer shut down before a reply arrived"),
+            Self::Confirmation(msg) => write!(f, "unexpected publisher confirmation: {}", msg),
+            Self::MalformedReply(msg) => write!(f, "malformed reply: {}", msg),
+            Self::MalformedRequest(msg) => write!(f, "malformed request: {}", msg),
+            Self::MissingReplyTo => write!(f, "request is missing the reply_to property"),
+            Self::MissingCorrelationId => write!(f, "message is missing the correlation_id property"),
+            Self::Remote(msg) => write!(f, "server error: {}", msg),
+            Self::Overflow(idx) => write!(f, "fib({}) is too large to fit in a u64", idx),
+            Self::Codec(msg) => write!(f, "codec error: {}", msg),
+            Self::MethodNotFound(name) => write!(f, "method not found: {}", name),
+            Self::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            Self::ConnectionLost { summary, source } => {
+                write!(f, "connection lost ({}): {}", summary, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connection(err) => Some(err),
+            Self::ConnectionLost { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<lapin::Error> for Error {
+    fn from(err: lapin::Error) -> Self {
+        Self::Connection(err)
+    }
+}
+
+/// Result type returned by the public api of the rpc crate
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,3 +1,6 @@
+use num_bigint::BigUint;
+
+use crate::{Error, Result};
 
 // Do the heavy lifting
 fn _fib(n: usize) -> (usize, usize) {
@@ -15,14 +18,108 @@ fn _fib(n: usize) -> (usize, usize) {
     }
 }
 
-/// Given an index into the fibonacci series, 
+/// Given an index into the fibonacci series,
 /// calculate said series and return the corresponding
-/// value. 
+/// value.
+///
+/// This overflows for indices past 93 (panicking in debug builds, and
+/// wrapping in release builds).
+#[deprecated(note = "overflows past fib(93); use `checked_fib` or `big_fib` instead")]
 pub fn fib(n:usize) -> usize {
     if n == 0 {
-        0 
+        0
     } else {
         let (_,b) = _fib(n-1);
         b
     }
 }
+
+/// Given an index into the fibonacci series, return the corresponding value,
+/// or `Error::Overflow` if it does not fit in a u64 (ie for indices past 93).
+pub fn checked_fib(n: u32) -> Result<u64> {
+    if n == 0 {
+        return Ok(0);
+    }
+    // the answer overflows long before walking the series gets slow
+    let (mut a, mut b) = (0u64, 1u64);
+    for _ in 1..n {
+        let next = a.checked_add(b).ok_or(Error::Overflow(n))?;
+        a = b;
+        b = next;
+    }
+    Ok(b)
+}
+
+// fast doubling, returning (fib(n), fib(n+1))
+fn _big_fib(n: u32) -> (BigUint, BigUint) {
+    if n == 0 {
+        (BigUint::from(0u32), BigUint::from(1u32))
+    } else {
+        let (a, b) = _big_fib(n / 2);
+        let c = &a * (&b * 2u32 - &a);
+        let d = &a * &a + &b * &b;
+        if n % 2 == 0 {
+            (c, d)
+        } else {
+            let e = &c + &d;
+            (d, e)
+        }
+    }
+}
+
+/// Given an index into the fibonacci series, return the exact corresponding value.
+pub fn big_fib(n: u32) -> BigUint {
+    _big_fib(n).0
+}
+
+/// Given an index into the fibonacci series, return the exact corresponding value
+/// as a decimal string.
+pub fn fib_decimal(n: u32) -> String {
+    big_fib(n).to_str_radix(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERIES: [u64; 11] = [0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 55];
+
+    #[test]
+    #[allow(deprecated)]
+    fn fib_given_small_index_matches_series() {
+        for (idx, expected) in SERIES.iter().enumerate() {
+            assert_eq!(fib(idx) as u64, *expected);
+            assert_eq!(checked_fib(idx as u32).unwrap(), *expected);
+            assert_eq!(fib_decimal(idx as u32), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn checked_fib_given_largest_index_fits() {
+        assert_eq!(checked_fib(93).unwrap(), 12_200_160_415_121_876_738);
+    }
+
+    #[test]
+    fn checked_fib_given_index_past_u64_fails() {
+        for idx in &[94, 95, 1000, u32::MAX] {
+            match checked_fib(*idx) {
+                Err(Error::Overflow(n)) => assert_eq!(n, *idx),
+                other => panic!("expected overflow for {}, got {:?}", idx, other),
+            }
+        }
+    }
+
+    #[test]
+    fn big_fib_agrees_with_checked_fib() {
+        for idx in 0..=93 {
+            assert_eq!(fib_decimal(idx), checked_fib(idx).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn big_fib_given_index_past_u64_is_exact() {
+        assert_eq!(fib_decimal(94), "19740274219868223167");
+        assert_eq!(fib_decimal(100), "354224848179261915075");
+        assert_eq!(fib_decimal(200), "280571172992510140037611932413038677189525");
+    }
+}
@@ -0,0 +1,213 @@
+//! handler
+//!
+//! # Handler
+//! The trait implemented by the procedures served by an `RpcServer`, along
+//! with `FibHandler`, which calculates fibonacci values, and `TypedHandler`,
+//! which adapts a function over serde types, and `Router`, which serves several
+//! named methods on one queue.
+use lapin::BasicProperties;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use tracing::{debug, info};
+
+use crate::{fib_decimal, Error, Result, MAX_FIB_INDEX};
+use crate::codec::Codec;
+
+/// A request received by the server
+#[derive(Debug)]
+pub struct Request<'a> {
+    /// The raw payload of the request
+    pub data: &'a [u8],
+    /// The properties the request was published with
+    pub properties: &'a BasicProperties,
+}
+
+impl<'a> Request<'a> {
+    /// The codec identified by the request's `content_type`. Requests without a
+    /// `content_type` are assumed to be json.
+    pub fn codec(&self) -> Result<Codec> {
+        match self.properties.content_type() {
+            Some(content_type) => Codec::from_content_type(content_type.as_str())
+                .ok_or_else(|| Error::MalformedRequest(
+                    format!("unsupported content type: {}", content_type.as_str())
+                )),
+            None => Ok(Codec::default()),
+        }
+    }
+
+    /// The name of the method requested, via the `type` property, if any
+    pub fn method(&self) -> Option<&str> {
+        self.properties.kind().as_ref().map(|kind| kind.as_str())
+    }
+}
+
+/// A remote procedure, which maps the payload of a request to the payload
+/// of its reply.
+pub trait Handler: Send + Sync + 'static {
+    /// Produce the payload of the reply to the supplied request. An error is
+    /// sent back to the client as an error reply.
+    fn handle(&self, request: &Request<'_>) -> Result<Vec<u8>>;
+}
+
+/// Any suitable closure may serve as a Handler
+impl<F> Handler for F
+where
+    F: Fn(&Request<'_>) -> Result<Vec<u8>> + Send + Sync + 'static
+{
+    fn handle(&self, request: &Request<'_>) -> Result<Vec<u8>> {
+        self(request)
+    }
+}
+
+/// Handler which parses an index into the fibonacci series from the request, and
+/// replies with the exact value at that index as a decimal string.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FibHandler;
+
+impl Handler for FibHandler {
+    fn handle(&self, request: &Request<'_>) -> Result<Vec<u8>> {
+        // perhaps we can use bytevec crate to
+        // skip the string
+        let value = std::str::from_utf8(request.data)
+            .map_err(|_| Error::MalformedRequest("unable to convert raw data from delivery to string".into()))?;
+        let intvalue = value.parse::<u32>()
+            .map_err(|_| Error::MalformedRequest(format!("unable to convert {} to an int", value)))?;
+        if intvalue > MAX_FIB_INDEX {
+            return Err(Error::MalformedRequest(
+                format!("index {} exceeds the maximum of {}", intvalue, MAX_FIB_INDEX)
+            ));
+        }
+
+        info!("[.] Calculating fib({})", intvalue);
+        let result = fib_decimal(intvalue);
+        // the exact value may run to thousands of digits
+        debug!("[X] fib({}) = {}", intvalue, result);
+        Ok(result.into_bytes())
+    }
+}
+
+/// Handler which decodes the request into `Req`, hands it to the wrapped function,
+/// and encodes the `Resp` it returns. The codec is chosen by the `content_type` of
+/// the request, and the same codec is used for the reply.
+pub struct TypedHandler<Req, Resp, F> {
+    func: F,
+    _marker: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req, Resp, F> TypedHandler<Req, Resp, F>
+where
+    F: Fn(Req) -> Result<Resp>
+{
+    /// Create a new TypedHandler wrapping the supplied function
+    pub fn new(func: F) -> Self {
+        Self { func, _marker: PhantomData }
+    }
+}
+
+impl<Req, Resp, F> Handler for TypedHandler<Req, Resp, F>
+where
+    Req: DeserializeOwned + 'static,
+    Resp: Serialize + 'static,
+    F: Fn(Req) -> Result<Resp> + Send + Sync + 'static
+{
+    fn handle(&self, request: &Request<'_>) -> Result<Vec<u8>> {
+        let codec = request.codec()?;
+        let value: Req = codec.decode(request.data)
+            .map_err(|e| Error::MalformedRequest(e.to_string()))?;
+        let response = (self.func)(value)?;
+        codec.encode(&response)
+    }
+}
+
+/// Handler which serves several named methods, dispatching each request to the
+/// method named by its `type` property. Requests naming a method which has not
+/// been registered are answered with a "method not found" error reply. Requests
+/// which do not name a method are served by the fallback, if one has been set.
+#[derive(Default)]
+pub struct Router {
+    methods: HashMap<String, Box<dyn Handler>>,
+    fallback: Option<Box<dyn Handler>>,
+}
+
+impl Router {
+    /// Create a new Router without any methods
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler for the named method
+    pub fn method(mut self, name: impl Into<String>, handler: impl Handler) -> Self {
+        self.methods.insert(name.into(), Box::new(handler));
+        self
+    }
+
+    /// Register the handler for requests which do not name a method
+    pub fn fallback(mut self, handler: impl Handler) -> Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Retrieve the names of the registered methods
+    pub fn methods(&self) -> Vec<&str> {
+        let mut names = self.methods.keys().map(|name| name.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, request: &Request<'_>) -> Result<Vec<u8>> {
+        let handler = match request.method() {
+            Some(name) => self.methods.get(name)
+                .ok_or_else(|| Error::MethodNotFound(name.to_string()))?,
+            None => self.fallback.as_ref()
+                .ok_or_else(|| Error::MethodNotFound("<unnamed>".to_string()))?,
+        };
+        handler.handle(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_for<'a>(properties: &'a BasicProperties) -> Request<'a> {
+        Request { data: b"21", properties }
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .method("echo", |request: &Request<'_>| -> Result<Vec<u8>> { Ok(request.data.to_vec()) })
+            .method("fib", FibHandler)
+    }
+
+    #[test]
+    fn router_given_known_method_dispatches() {
+        let properties = BasicProperties::default().with_kind("fib".into());
+        let reply = router().handle(&request_for(&properties)).unwrap();
+        assert_eq!(reply, b"10946".to_vec());
+    }
+
+    #[test]
+    fn router_given_unknown_method_fails() {
+        let properties = BasicProperties::default().with_kind("nope".into());
+        match router().handle(&request_for(&properties)) {
+            Err(Error::MethodNotFound(name)) => assert_eq!(name, "nope"),
+            other => panic!("expected method not found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn router_given_no_method_uses_fallback() {
+        let properties = BasicProperties::default();
+        assert!(router().handle(&request_for(&properties)).is_err());
+        let reply = router().fallback(FibHandler).handle(&request_for(&properties)).unwrap();
+        assert_eq!(reply, b"10946".to_vec());
+    }
+
+    #[test]
+    fn router_lists_methods() {
+        assert_eq!(router().methods(), vec!["echo", "fib"]);
+    }
+}
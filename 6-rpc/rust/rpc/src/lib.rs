@@ -20,6 +20,33 @@
 //! queue as dictated by the `reply_to` property. It will also set the `correlation_id` 
 //! from the incoming message property.
 //!
+//! The Server is generic over a `Handler`, which maps the payload of a request to the
+//! payload of the reply. `FibRpcServer` is simply an `RpcServer` serving `FibHandler`. New
+//! remote procedures may be served by implementing `Handler` (or supplying a closure).
+//!
+//! Procedures which take and return structured data may be served via `TypedHandler`, and 
+//! called via `RpcClient`, which encode requests and replies via serde, using the `Codec` 
+//! (json or bincode) identified by the message's `content_type`.
+//!
+//! Servers are most readily configured via `RpcServerBuilder` (or `FibRpcServerBuilder`), which
+//! offers named settings for the queue, prefetch count, AMQP address, and so on, validating them
+//! before connecting.
+//!
+//! Several procedures may share one queue by serving a `Router`, which dispatches each request
+//! to the method named by its `type` property. `MethodClient::call(method, args)` names the method.
+//!
+//! Connections are described by a `ConnectionConfig` (uri, vhost, credentials, heartbeat, etc),
+//! which may be read from a TOML file or from the `AMQP_*` environment variables.
+//!
+//! Logging setup, the `LogLevel` type, TLS settings and shutdown handling are shared with the
+//! other tutorials via the `rabbit-common` crate, and re-exported here where they form part of
+//! this crate's api.
+//!
+//! Both structs offer an async api (eg `FibClient::connect().await` and `client.call(n).await`),
+//! which may be used from within an async application, along with a blocking api 
+//! (eg `FibClient::new()` and `client.fib(n)`) which is a thin wrapper around it. The blocking
+//! api must not be called from within an async context, as it blocks the current thread.
+//!
 //! Because Lapin is an async api, this will be slightly trickier than if it were sync.
 //! One of the interesting challenges is that the Client code which processes the results 
 //! must do so in a separate tag - potentially even a separate thread - depending upon the 
@@ -34,86 +61,115 @@
 //! ## Interesting Crates
 //! In addition to `Lapin`, there are a number of other crates used herein. 
 //! - tracing: Used to handle logging duties, `tracing` has a reputation for making 
-//! tracing logs through an async ecosystem easier. i have no complaints.
+//!   tracing logs through an async ecosystem easier. i have no complaints.
 //! - uuid: Used to generate the uuid string. Straightforward.
+//! - serde: Used, along with serde_json and bincode, to encode typed requests and replies.
 //! - structopt: My goto wrapper around clap making it really trivial to generate clis.
 //! - strum: THis crate provides a number of procedural macros which make dealing with 
-//! simple enums simple. I use it to convert back and forth between strings and variants. Its 
-//! great for helping to provide more typesafety to an api which would otherwise be stringly
-//! typed (due to laziness mostly)
+//!   simple enums simple. I use it to convert back and forth between strings and variants. Its 
+//!   great for helping to provide more typesafety to an api which would otherwise be stringly
+//!   typed (due to laziness mostly)
 //! 
 use async_std::task;
 use lapin::{
     Channel,
     Connection,
-    ConnectionProperties, 
-    Result as AsyncResult
 };
-use std::env;
-use std::iter::Iterator;
 use tracing::{info};
 
+pub use rabbit_common::amqp_addr;
+
 pub mod fib;
+pub use fib::{checked_fib, big_fib, fib_decimal};
+#[allow(deprecated)]
 pub use fib::fib;
 
 pub mod constants;
 pub use constants::*;
 
-pub mod log_level;
-pub use log_level::LogLevel;
+pub mod error;
+pub use error::{Error, Result};
+
+pub mod config;
+pub use config::ConnectionConfig;
+
+pub mod protocol;
+
+pub mod codec;
+pub use codec::Codec;
+
+pub use rabbit_common::LogLevel;
 
 pub mod rpc_client;
-pub use rpc_client::FibClient;
+pub use rpc_client::{FibClient, RpcClient, MethodClient};
+
+pub mod handler;
+pub use handler::{Handler, Request, FibHandler, TypedHandler, Router};
 
 pub mod rpc_server;
-pub use rpc_server::FibRpcServer;
-
-pub mod quit_service {
-    use std::io;
-    pub fn prompt() {
-        println!("Type q or exit to quit");
-        loop {
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-               Ok(_goes_into_input_above) => {},
-               Err(_no_updates_is_fine) => {},
-            }
-            let input = input.trim().to_string();
-            if input == "q" || input == "quit" || input == "exit" {
-               return 
-           }
-       }
-    }
-}
+pub use rpc_server::{RpcServer, FibRpcServer, ServeSummary};
+
+pub mod server_builder;
+pub use server_builder::{RpcServerBuilder, FibRpcServerBuilder};
+
+pub mod cache;
+pub use cache::{ResultCache, CacheStats};
+
+pub mod reconnect;
+pub use reconnect::Backoff;
+
+pub mod pool;
+pub use pool::WorkerPool;
+
+pub use rabbit_common::shutdown;
+pub use shutdown::ShutdownHandle;
 
 #[derive(Debug)]
 pub struct SimpleClient {
     pub conn: Connection,
     pub chan: Channel,
+    config: ConnectionConfig,
 }
 
 impl SimpleClient {
-    pub fn new() -> AsyncResult<Self> {
+    /// Connect to the RabbitMq server described by the `AMQP_*` environment
+    /// variables (see `ConnectionConfig::from_env`), falling back to `LOCALHOST`. 
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `SimpleClient::connect` instead.
+    pub fn new() -> Result<Self> {
+        task::block_on(Self::connect(ConnectionConfig::from_env()?))
+    }
 
-        task::block_on(async {
-            let addr = env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into());
-            
-            let conn = Connection::connect(
-                &addr,
-                ConnectionProperties::default(),
-            )
-            .await?;
+    /// Connect to the RabbitMq server described by the supplied config, and create 
+    /// a channel. A uri (eg `"amqp://127.0.0.1:5672/%2f"`) may be supplied in place
+    /// of a config.
+    pub async fn connect(config: impl Into<ConnectionConfig>) -> Result<Self> {
+        let config = config.into();
+        // the uri carries the overrides from the config, so connect with it
+        // rather than with the string it was parsed from
+        let tls = config.tls_config()?;
+        let conn = Connection::connect_uri_with_identity(
+            config.amqp_uri()?,
+            config.connection_properties(),
+            tls.as_ref(),
+        )
+        .await?;
 
-            info!("established connection to Rabbit server via {}", &addr);
+        info!("established connection to Rabbit server via {}", config.describe());
 
-            let chan = conn.create_channel().await?;
-            info!("created channel");
+        let chan = conn.create_channel().await?;
+        info!("created channel");
 
-        
         Ok(Self {
-                conn,
-                chan,
-            })
+            conn,
+            chan,
+            config,
         })
     }
+
+    /// Retrieve the config used to connect
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
 }
@@ -0,0 +1,125 @@
+//! pool
+//!
+//! # Pool
+//! A bounded pool of spawned tasks, used by the server to work on several
+//! requests at once without taking on more than it was asked to.
+use async_std::task::{self, JoinHandle};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+
+/// Runs up to `limit` tasks at once, handing back their outputs as they finish.
+#[derive(Debug)]
+pub struct WorkerPool<T> {
+    limit: usize,
+    running: FuturesUnordered<JoinHandle<T>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Create a pool running at most `limit` tasks at once. A limit of 0 is
+    /// treated as 1.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            running: FuturesUnordered::new(),
+        }
+    }
+
+    /// The maximum number of tasks run at once
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of tasks which have been spawned, and whose output has not
+    /// yet been collected
+    pub fn len(&self) -> usize {
+        self.running.len()
+    }
+
+    /// Is the pool without any tasks?
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    /// Spawn the supplied task, first waiting for a free slot if the pool is full.
+    /// Returns the outputs of any tasks collected while waiting.
+    pub async fn spawn<F>(&mut self, job: F) -> Vec<T>
+    where
+        F: Future<Output = T> + Send + 'static
+    {
+        let mut finished = Vec::new();
+        while self.running.len() >= self.limit {
+            match self.running.next().await {
+                Some(output) => finished.push(output),
+                None => break,
+            }
+        }
+        self.running.push(task::spawn(job));
+        finished
+    }
+
+    /// Wait for the next task to finish, returning its output, or None if the
+    /// pool is empty
+    pub async fn next(&mut self) -> Option<T> {
+        self.running.next().await
+    }
+
+    /// Wait for every task to finish, returning their outputs
+    pub async fn drain(&mut self) -> Vec<T> {
+        let mut finished = Vec::with_capacity(self.running.len());
+        while let Some(output) = self.running.next().await {
+            finished.push(output);
+        }
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const JOB_TIME: Duration = Duration::from_millis(200);
+
+    // a slow, blocking job, like a handler calculating a large fibonacci value
+    async fn slow_job(id: usize) -> usize {
+        task::spawn_blocking(move || {
+            thread::sleep(JOB_TIME);
+            id
+        })
+        .await
+    }
+
+    fn run_jobs(limit: usize, count: usize) -> (Vec<usize>, Duration) {
+        task::block_on(async {
+            let start = Instant::now();
+            let mut pool = WorkerPool::new(limit);
+            let mut finished = Vec::new();
+            for id in 0..count {
+                finished.extend(pool.spawn(slow_job(id)).await);
+            }
+            finished.extend(pool.drain().await);
+            finished.sort();
+            (finished, start.elapsed())
+        })
+    }
+
+    #[test]
+    fn slow_jobs_run_concurrently() {
+        let (finished, elapsed) = run_jobs(4, 4);
+        assert_eq!(finished, vec![0, 1, 2, 3]);
+        assert!(elapsed < JOB_TIME * 2, "took {:?}", elapsed);
+    }
+
+    #[test]
+    fn jobs_beyond_limit_wait_for_a_slot() {
+        let (finished, elapsed) = run_jobs(2, 4);
+        assert_eq!(finished, vec![0, 1, 2, 3]);
+        assert!(elapsed >= JOB_TIME * 2, "took {:?}", elapsed);
+    }
+
+    #[test]
+    fn zero_limit_runs_one_at_a_time() {
+        assert_eq!(WorkerPool::<()>::new(0).limit(), 1);
+    }
+}
@@ -0,0 +1,147 @@
+//! protocol
+//!
+//! # Protocol
+//! Conventions shared by the client and server beyond the payload itself.
+//!
+//! Every reply carries a `status` header. A status of `ok` indicates that the
+//! payload holds the result, while any other status indicates that the server
+//! rejected the request, and that the payload holds a human readable explanation.
+//! A status of `method_not_found` indicates that the request named a method (via
+//! its `type` property) which the server does not provide.
+//!
+//! A request may carry a deadline, in the `x-deadline` header, as milliseconds since
+//! the unix epoch. The server skips requests whose deadline has passed, as nobody is
+//! waiting for their reply. Such requests also carry an `expiration`, so that the
+//! broker discards them if they are still queued when the deadline passes.
+use lapin::{
+    BasicProperties,
+    types::{AMQPValue, FieldTable, ShortString},
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, Result};
+
+/// Name of the header carrying the status of a reply
+pub const STATUS_HEADER: &str = "status";
+/// Status of a reply whose payload holds the result
+pub const STATUS_OK: &str = "ok";
+/// Status of a reply whose payload holds an error message
+pub const STATUS_ERROR: &str = "error";
+/// Status of a reply to a request naming an unknown method. The payload holds the
+/// name of the method
+pub const STATUS_METHOD_NOT_FOUND: &str = "method_not_found";
+
+/// Name of the header carrying the deadline of a request, in milliseconds since
+/// the unix epoch
+pub const DEADLINE_HEADER: &str = "x-deadline";
+
+/// Build the headers for a reply with the supplied status
+pub fn status_headers(status: &str) -> FieldTable {
+    let mut headers = FieldTable::default();
+    headers.insert(ShortString::from(STATUS_HEADER), AMQPValue::LongString(status.into()));
+    headers
+}
+
+/// The status a server should reply with, given the outcome of a request
+pub fn status_of<T>(outcome: &Result<T>) -> &'static str {
+    match outcome {
+        Ok(_) => STATUS_OK,
+        Err(Error::MethodNotFound(_)) => STATUS_METHOD_NOT_FOUND,
+        Err(_) => STATUS_ERROR,
+    }
+}
+
+/// Does the reply described by the supplied properties carry an error?
+pub fn is_error_reply(properties: &BasicProperties) -> bool {
+    match header_str(properties, STATUS_HEADER) {
+        Some(status) => status != STATUS_OK,
+        None => false,
+    }
+}
+
+/// Interpret a reply, given its properties and payload, converting error replies
+/// into the corresponding Error.
+pub fn reply_result(properties: &BasicProperties, data: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_error_reply(properties) {
+        return Ok(data);
+    }
+    let msg = String::from_utf8_lossy(&data).into_owned();
+    match header_str(properties, STATUS_HEADER) {
+        Some(STATUS_METHOD_NOT_FOUND) => Err(Error::MethodNotFound(msg)),
+        _ => Err(Error::Remote(msg)),
+    }
+}
+
+/// Add a deadline `timeout` from now to the supplied request properties, via both
+/// the deadline header and the `expiration` property
+pub fn with_deadline(properties: BasicProperties, timeout: Duration) -> BasicProperties {
+    let deadline = SystemTime::now() + timeout;
+    let millis = deadline.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(ShortString::from(DEADLINE_HEADER), AMQPValue::LongLongInt(millis as i64));
+    // an expiration of 0 would only deliver the request to a consumer which is
+    // waiting at that very moment
+    let expiration = timeout.as_millis().max(1);
+    properties
+        .with_headers(headers)
+        .with_expiration(ShortString::from(expiration.to_string()))
+}
+
+/// Retrieve the deadline of a request, if it carries one
+pub fn deadline_of(properties: &BasicProperties) -> Option<SystemTime> {
+    let headers = properties.headers().as_ref()?;
+    let millis = match headers.inner().get(&ShortString::from(DEADLINE_HEADER))? {
+        AMQPValue::LongLongInt(value) if *value >= 0 => *value as u64,
+        AMQPValue::LongUInt(value) => *value as u64,
+        AMQPValue::Timestamp(value) => *value,
+        AMQPValue::LongString(value) => value.as_str().parse().ok()?,
+        AMQPValue::ShortString(value) => value.as_str().parse().ok()?,
+        _ => return None,
+    };
+    Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Has the deadline of the request described by the supplied properties passed
+/// as of `now`? Requests without a deadline never expire.
+pub fn is_expired(properties: &BasicProperties, now: SystemTime) -> bool {
+    match deadline_of(properties) {
+        Some(deadline) => deadline <= now,
+        None => false,
+    }
+}
+
+/// Retrieve the named header as a &str, if it is present and is a string
+pub fn header_str<'a>(properties: &'a BasicProperties, name: &str) -> Option<&'a str> {
+    let headers = properties.headers().as_ref()?;
+    match headers.inner().get(&ShortString::from(name))? {
+        AMQPValue::LongString(value) => Some(value.as_str()),
+        AMQPValue::ShortString(value) => Some(value.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_deadline_sets_header_and_expiration() {
+        let properties = with_deadline(BasicProperties::default(), Duration::from_secs(5));
+        assert_eq!(properties.expiration().as_ref().map(|e| e.as_str()), Some("5000"));
+        let deadline = deadline_of(&properties).unwrap();
+        assert!(deadline > SystemTime::now());
+    }
+
+    #[test]
+    fn is_expired_given_passed_deadline() {
+        let properties = with_deadline(BasicProperties::default(), Duration::from_secs(5));
+        assert!(!is_expired(&properties, SystemTime::now()));
+        assert!(is_expired(&properties, SystemTime::now() + Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn is_expired_given_no_deadline_is_false() {
+        let far_future = SystemTime::now() + Duration::from_secs(60 * 60 * 24 * 365);
+        assert!(!is_expired(&BasicProperties::default(), far_future));
+    }
+}
@@ -0,0 +1,109 @@
+//! reconnect
+//!
+//! # Reconnect
+//! Exponential backoff, used to reconnect to RabbitMq after the connection is
+//! lost, eg while the broker restarts.
+use async_std::task;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{ConnectionConfig, Result, SimpleClient};
+
+/// How long to wait between attempts to connect. The delay starts at `initial`,
+/// and is multiplied by `multiplier` after each failed attempt, up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Delay before the first attempt to reconnect
+    pub initial: Duration,
+    /// Longest delay between attempts
+    pub max: Duration,
+    /// Factor by which the delay grows after each failed attempt
+    pub multiplier: u32,
+    /// Number of attempts after which to give up. If None, attempts continue
+    /// indefinitely.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay before the supplied attempt (counting from 0), or None if no
+    /// further attempts should be made
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if let Some(max_attempts) = self.max_attempts {
+            if attempt >= max_attempts {
+                return None;
+            }
+        }
+        let factor = self.multiplier.checked_pow(attempt).unwrap_or(u32::MAX);
+        let delay = self.initial.checked_mul(factor).unwrap_or(self.max);
+        Some(delay.min(self.max))
+    }
+}
+
+/// Connect to the RabbitMq server described by the supplied config, retrying
+/// with the supplied backoff until a connection is established, or the backoff
+/// gives up, in which case the last error is returned.
+pub async fn connect_with_backoff(config: &ConnectionConfig, backoff: &Backoff) -> Result<SimpleClient> {
+    let mut attempt = 0;
+    loop {
+        match SimpleClient::connect(config.clone()).await {
+            Ok(client) => {
+                info!(attempt, server = %config.describe(), "connected");
+                return Ok(client);
+            }
+            Err(err) => match backoff.delay(attempt) {
+                Some(delay) => {
+                    warn!(attempt, ?delay, error = %err, server = %config.describe(), "unable to connect, retrying");
+                    task::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => {
+                    warn!(attempt, error = %err, server = %config.describe(), "unable to connect, giving up");
+                    return Err(err);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2,
+            max_attempts: None,
+        };
+        let delays = (0..6).map(|attempt| backoff.delay(attempt).unwrap()).collect::<Vec<_>>();
+        assert_eq!(delays, vec![
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(400),
+            Duration::from_millis(800),
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        ]);
+        assert_eq!(backoff.delay(1000), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn delay_given_exhausted_attempts_is_none() {
+        let backoff = Backoff { max_attempts: Some(2), ..Backoff::default() };
+        assert!(backoff.delay(1).is_some());
+        assert!(backoff.delay(2).is_none());
+    }
+}
@@ -1,136 +1,758 @@
+use async_std::future;
 use async_std::sync::Arc;
 use async_std::sync::Mutex;
 use async_std::task;
+use futures::channel::oneshot;
+use futures::stream;
+use futures::StreamExt;
 use lapin::{
     BasicProperties,
-    options::*, 
-    publisher_confirm::Confirmation, 
-    Result as AsyncResult,
+    Consumer,
+    options::*,
+    publisher_confirm::Confirmation,
     types as ampt,
-    types::FieldTable, 
+    types::FieldTable,
 };
-use std::iter::Iterator;
-use tracing::{info, error};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{debug, info, error, warn};
 use uuid::Uuid;
 
-use crate::{SimpleClient, QUEUE};
+use crate::{SimpleClient, ConnectionConfig, QUEUE, Error, Result};
+use crate::codec::Codec;
+use crate::protocol::{reply_result, with_deadline};
+use crate::reconnect::{connect_with_backoff, Backoff};
 
+// the most requests `FibClient::call_many` keeps waiting on a reply at once, so
+// a long batch does not flood the server's queue
+const MAX_IN_FLIGHT: usize = 64;
 
-/// A Fibonacci Client
-pub struct FibClient {
-    inner: SimpleClient
+// callers waiting on a reply, keyed by the correlation id of their request
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<Result<Vec<u8>>>>>>;
+
+// The backoff used by the clients to reconnect, unless another is set. Unlike the
+// server, a client gives up after a few attempts, so that calls fail rather than
+// wait indefinitely for the broker to come back.
+fn default_reconnect() -> Option<Backoff> {
+    Some(Backoff { max_attempts: Some(5), ..Backoff::default() })
 }
 
-impl FibClient {
-    /// Create a new instance of Client
-    pub fn new() -> AsyncResult<Self> {
-        let inner = SimpleClient::new()?;
-        Ok(Self{inner})
-    }
-    
-    /// Request an item from the fibonacci series given an index.
-    pub fn fib(&self, input: u32) -> AsyncResult<usize> {
-        task::block_on(async {
-            let input = input.to_string();
-            let queue = self.inner.chan
-            .queue_declare(
+// A connection, along with the reply queue and consumer declared over it.
+// Replies are routed to the waiting caller via the correlation id of the request.
+struct ReplySession {
+    inner: SimpleClient,
+    reply_queue: ampt::ShortString,
+    consumer_tag: ampt::ShortString,
+    pending: Pending,
+    // set by the dispatcher once the reply consumer has gone away
+    closed: Arc<AtomicBool>,
+}
+
+impl ReplySession {
+    // declare the reply queue and start routing replies to callers
+    async fn setup(inner: SimpleClient) -> Result<Self> {
+        let queue = inner.chan
+        .queue_declare(
+            "",
+            QueueDeclareOptions{
+                durable: false,
+                exclusive:true,
+                auto_delete: false,
+                nowait: false,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+        info!("Declared reply queue '{}'", queue.name().as_str());
+
+        // consumer
+        let  consumer = inner.chan.basic_consume(
+            queue.name().as_str(), //queue
+            "", // consumer tag
+            BasicConsumeOptions{
+                no_local: false,
+                no_ack: true,
+                exclusive: false,
+                nowait: false,
+            },
+            FieldTable::default()
+        ).await?;
+        let consumer_tag = consumer.tag();
+
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+        task::spawn(dispatch(consumer, pending.clone(), closed.clone()));
+
+        Ok(Self{
+            inner,
+            reply_queue: queue.name().clone(),
+            consumer_tag,
+            pending,
+            closed,
+        })
+    }
+
+    // has the reply consumer, and so most likely the connection, gone away?
+    fn is_lost(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) || !self.inner.chan.status().connected()
+    }
+}
+
+// The correlation-id/reply_to machinery shared by the clients.
+//
+// A single exclusive reply queue, and a single consumer for it, are declared
+// when the channel is constructed. Replies are routed to the waiting caller
+// via the correlation id of the request, so many calls - even from
+// different tasks - may be in flight at once.
+//
+// If the connection is lost, eg because the broker restarted, the requests in
+// flight fail with `Error::Disconnected`, and the next request reconnects with
+// exponential backoff, redeclaring the reply queue and consuming it again.
+struct ReplyChannel {
+    session: RwLock<Arc<ReplySession>>,
+    // held while reconnecting, so that only one caller does so
+    reconnecting: Mutex<()>,
+    reconnect: Option<Backoff>,
+}
+
+impl ReplyChannel {
+    // declare the reply queue and start routing replies to callers
+    async fn setup(inner: SimpleClient) -> Result<Self> {
+        let session = ReplySession::setup(inner).await?;
+        Ok(Self{
+            session: RwLock::new(Arc::new(session)),
+            reconnecting: Mutex::new(()),
+            reconnect: default_reconnect(),
+        })
+    }
+
+    // the current session, whether or not it has been lost
+    fn current(&self) -> Arc<ReplySession> {
+        self.session.read().unwrap().clone()
+    }
+
+    // the name of the current reply queue, which changes when the client reconnects
+    fn reply_queue(&self) -> String {
+        self.current().reply_queue.as_str().to_string()
+    }
+
+    // the current session, reconnecting first if it has been lost. Callers
+    // arriving while a reconnect is under way wait for it to finish.
+    async fn session(&self) -> Result<Arc<ReplySession>> {
+        let session = self.current();
+        if !session.is_lost() {
+            return Ok(session);
+        }
+        let backoff = match &self.reconnect {
+            Some(backoff) => backoff,
+            None => return Err(Error::Disconnected),
+        };
+        let _reconnecting = self.reconnecting.lock().await;
+        // another caller may have reconnected while we waited
+        let session = self.current();
+        if !session.is_lost() {
+            return Ok(session);
+        }
+        warn!(reply_queue = session.reply_queue.as_str(), "reply channel lost, reconnecting");
+        // only the channel may have died, so close the connection rather than leak it
+        if session.inner.conn.status().connected() {
+            if let Err(err) = session.inner.conn.close(200, "reconnecting").await {
+                warn!(error = %err, "unable to close the lost connection");
+            }
+        }
+        let client = connect_with_backoff(session.inner.config(), backoff).await?;
+        let session = Arc::new(ReplySession::setup(client).await?);
+        *self.session.write().unwrap() = session.clone();
+        info!(reply_queue = session.reply_queue.as_str(), "reconnected");
+        Ok(session)
+    }
+
+    // cancel the reply consumer and delete the reply queue
+    async fn shutdown(self) -> Result<()> {
+        let session = self.current();
+        session.inner.chan
+            .basic_cancel(session.consumer_tag.as_str(), BasicCancelOptions::default())
+            .await?;
+        session.inner.chan
+            .queue_delete(session.reply_queue.as_str(), QueueDeleteOptions::default())
+            .await?;
+        Ok(())
+    }
+
+    // publish the payload to the supplied queue and wait for the reply, optionally
+    // bounded by timeout. The correlation id and reply_to properties are added to
+    // the supplied properties, along with a deadline if there is a timeout, so that
+    // the server does not bother with requests we have given up on.
+    async fn request(
+        &self,
+        queue_name: &str,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+        timeout: Option<Duration>
+    ) -> Result<Vec<u8>> {
+        let session = self.session().await?;
+        // generate correlation id
+        let correlation_id = Uuid::new_v4().to_hyphenated().to_string();
+        // register interest in the reply before publishing, so that
+        // a quick reply cannot beat us to the map
+        let (sender, receiver) = oneshot::channel();
+        session.pending.lock().await.insert(correlation_id.clone(), sender);
+        let properties = match timeout {
+            Some(duration) => with_deadline(properties, duration),
+            None => properties,
+        };
+
+        let confirm = session.inner.chan
+            .basic_publish(
+                // exchange
                 "",
-                QueueDeclareOptions{
-                    durable: false,
-                    exclusive:true,
-                    auto_delete: false, 
-                    nowait: false,
-                    ..Default::default()
+                // routing key
+                queue_name,
+                // options
+                BasicPublishOptions{
+                    mandatory: false,
+                    immediate: false,
                 },
-                FieldTable::default(),
+                // payload
+                payload,
+                // properties
+                properties
+                    .with_correlation_id(ampt::ShortString::from(correlation_id.clone()))
+                    .with_reply_to(session.reply_queue.clone())
+                    ,
             )
-            .await?;
+            .await;
+        // two awaits to get from a doubly wrapped Result
+        let confirm = match confirm {
+            Ok(confirm) => confirm.await,
+            Err(err) => Err(err),
+        };
+        let confirm = match confirm {
+            Ok(confirm) => confirm,
+            Err(err) => {
+                session.pending.lock().await.remove(&correlation_id);
+                return Err(err.into());
+            }
+        };
+        // confirms are not enabled on the channel, so anything else means
+        // the request may not have been delivered
+        if confirm != Confirmation::NotRequested {
+            session.pending.lock().await.remove(&correlation_id);
+            return Err(Error::Confirmation(format!("{:?}", confirm)));
+        }
 
-            info!( "Declared queue");
-            
-            // consumer
-            let  consumer = self.inner.chan.basic_consume(
-                queue.name().as_str(), //queue
-                "", // consumer tag
-                BasicConsumeOptions{
-                    no_local: false,
-                    no_ack: true,
-                    exclusive: false,
-                    nowait: false,
-                }, 
-                FieldTable::default()
-            ).await?;
-
-            // generate correlation id
-            let v: Option<usize> = None;
-            let fibval = Arc::new(Mutex::new(v));
-
-            let correlation_id = Uuid::new_v4().to_hyphenated().to_string();
-            let input_c = input.clone();
-            let cid = correlation_id.clone();
-            let fibval_c = fibval.clone();
-            let handle = task::spawn(async move {
-                let mut consumer_iter = consumer.into_iter();
-                while let Some(delivery_result) = consumer_iter.next() {
-                    if let Ok((_channel, delivery)) = delivery_result {
-                    
-                        if let Some(cor_id) = delivery.properties.correlation_id() {
-                            if cor_id.as_str() == &cid {
-                                let val = std::str::from_utf8(&delivery.data);
-                                if let Ok(value) = val {
-                                    info!("[x] calculating fib({})", input_c);
-                                    let value = value.parse::<usize>().unwrap();
-                                    *fibval_c.lock().await = Some(value);
-                                    // this is where the break should be
-                                    break;
-                                } else {
-                                    error!("unable to convert raw data from delivery to string");
-                                }
-                            } else {
-                                error!("Correlation ids do not match");
-                            }
-                        } else {
-                            error!("Error unwrapping correlation id {:#?}", delivery.properties.correlation_id());
-                        }
-                    
-                    } else {
-                        error!("unable to convert raw data from delivery to string");
-                    }
-                    //break;
+        let reply = match timeout {
+            Some(duration) => match future::timeout(duration, receiver).await {
+                Ok(reply) => reply,
+                Err(_) => {
+                    warn!("no reply received within {:?} for request {}", duration, correlation_id);
+                    // a late reply is simply dropped by the dispatcher
+                    session.pending.lock().await.remove(&correlation_id);
+                    return Err(Error::Timeout(duration));
                 }
+            },
+            None => receiver.await,
+        };
+        // the outer error indicates that the dispatcher went away, the inner
+        // that the server rejected the request
+        reply.map_err(|_| Error::Disconnected)?
+    }
+
+    // encode the request, publish it, optionally naming the method via the `type`
+    // property, and decode the reply
+    async fn typed_request<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        queue_name: &str,
+        codec: Codec,
+        method: Option<&str>,
+        request: &Req,
+        timeout: Option<Duration>
+    ) -> Result<Resp> {
+        let payload = codec.encode(request)?;
+        let mut properties = BasicProperties::default()
+            .with_content_type(ampt::ShortString::from(codec.content_type()));
+        if let Some(method) = method {
+            properties = properties.with_kind(ampt::ShortString::from(method));
+        }
+        let data = self.request(queue_name, payload, properties, timeout).await?;
+        codec.decode(&data)
+    }
+}
+
+/// A Fibonacci Client
+///
+/// The client declares a single exclusive reply queue, and a single consumer
+/// for it, when it is constructed. Replies are routed to the waiting caller
+/// via the correlation id of the request, so many calls - even from
+/// different tasks - may be in flight at once.
+pub struct FibClient {
+    channel: ReplyChannel,
+    timeout: Option<Duration>,
+}
+
+impl FibClient {
+    /// Create a new instance of Client.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::connect` instead.
+    pub fn new() -> Result<Self> {
+        task::block_on(Self::connect())
+    }
+
+    /// Connect to the RabbitMq server described by the `AMQP_*` environment
+    /// variables (see `ConnectionConfig::from_env`), and create a new instance of Client
+    pub async fn connect() -> Result<Self> {
+        let inner = SimpleClient::connect(ConnectionConfig::from_env()?).await?;
+        Self::from_client(inner).await
+    }
+
+    /// Create a new instance of Client from an existing SimpleClient
+    pub async fn from_client(inner: SimpleClient) -> Result<Self> {
+        let channel = ReplyChannel::setup(inner).await?;
+        Ok(Self{channel, timeout: None})
+    }
+
+    /// Set the default timeout used by `fib`. If None is supplied,
+    /// `fib` will wait for a reply indefinitely
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Retrieve the default timeout
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set the backoff used to reconnect when the connection to RabbitMq is lost.
+    /// By default, up to 5 attempts are made. If None is supplied, requests fail
+    /// with `Error::Disconnected` instead.
+    pub fn set_reconnect(&mut self, backoff: Option<Backoff>) {
+        self.channel.reconnect = backoff;
+    }
+
+    /// Retrieve the backoff used to reconnect, if the client reconnects
+    pub fn reconnect(&self) -> Option<&Backoff> {
+        self.channel.reconnect.as_ref()
+    }
+
+    /// Retrieve the name of the reply queue shared by all requests. A new reply
+    /// queue is declared whenever the client reconnects.
+    pub fn reply_queue(&self) -> String {
+        self.channel.reply_queue()
+    }
+
+    /// Request an item from the fibonacci series given an index, waiting
+    /// at most as long as the default timeout, if one has been set. Fails
+    /// with `Error::Overflow` if the value does not fit in a u64.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::call` instead.
+    pub fn fib(&self, input: u32) -> Result<u64> {
+        task::block_on(self.call(input))
+    }
+
+    /// Request an item from the fibonacci series given an index, giving up
+    /// with `Error::Timeout` if no reply arrives within `timeout`. Fails
+    /// with `Error::Overflow` if the value does not fit in a u64.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::call_with_timeout` instead.
+    pub fn fib_with_timeout(&self, input: u32, timeout: Duration) -> Result<u64> {
+        task::block_on(self.call_with_timeout(input, timeout))
+    }
+
+    /// Request the exact value of an item from the fibonacci series given an index,
+    /// as a decimal string, waiting at most as long as the default timeout, if one
+    /// has been set.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::call_big` instead.
+    pub fn fib_big(&self, input: u32) -> Result<String> {
+        task::block_on(self.call_big(input))
+    }
+
+    /// Request the items from the fibonacci series at each of the supplied indices,
+    /// returning the results in the same order. The requests share the one
+    /// connection and reply queue, a bounded number of them are in flight at once,
+    /// and each waits at most as long as the default timeout, if one has been set.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::call_many` instead.
+    pub fn fib_many(&self, inputs: &[u32]) -> Vec<Result<u64>> {
+        task::block_on(self.call_many(inputs))
+    }
+
+    /// Request the exact values of the items from the fibonacci series at each of
+    /// the supplied indices, as decimal strings, returning the results in the same
+    /// order. See `FibClient::fib_many`.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::call_many_big` instead.
+    pub fn fib_many_big(&self, inputs: &[u32]) -> Vec<Result<String>> {
+        task::block_on(self.call_many_big(inputs))
+    }
+
+    /// Request an item from the fibonacci series given an index, waiting
+    /// at most as long as the default timeout, if one has been set. Fails
+    /// with `Error::Overflow` if the value does not fit in a u64.
+    pub async fn call(&self, input: u32) -> Result<u64> {
+        let value = self.request(input, self.timeout).await?;
+        parse_checked(input, &value)
+    }
+
+    /// Request an item from the fibonacci series given an index, giving up
+    /// with `Error::Timeout` if no reply arrives within `timeout`. Fails
+    /// with `Error::Overflow` if the value does not fit in a u64.
+    pub async fn call_with_timeout(&self, input: u32, timeout: Duration) -> Result<u64> {
+        let value = self.request(input, Some(timeout)).await?;
+        parse_checked(input, &value)
+    }
+
+    /// Request the exact value of an item from the fibonacci series given an index,
+    /// as a decimal string, waiting at most as long as the default timeout, if one
+    /// has been set.
+    pub async fn call_big(&self, input: u32) -> Result<String> {
+        self.request(input, self.timeout).await
+    }
+
+    /// Request the exact value of an item from the fibonacci series given an index,
+    /// as a decimal string, giving up with `Error::Timeout` if no reply arrives
+    /// within `timeout`.
+    pub async fn call_big_with_timeout(&self, input: u32, timeout: Duration) -> Result<String> {
+        self.request(input, Some(timeout)).await
+    }
 
-            });
-
-            let confirm = self.inner.chan
-                .basic_publish(
-                    // exchange
-                    "", 
-                    // routing key
-                    QUEUE, 
-                    // options
-                    BasicPublishOptions{
-                        mandatory: false,
-                        immediate: false,
-                    },
-                    // payload
-                    input.as_bytes().to_vec(),
-                    // properties
-                    BasicProperties::default()
-                        .with_content_type(ampt::ShortString::from("text/plain"))
-                        .with_correlation_id(ampt::ShortString::from(correlation_id))
-                        .with_reply_to(queue.name().clone())
-                        ,
-                )
-                .await?
-                .await?; // two awaits to get from a doubly wrapped
-            // Result 
-            assert_eq!(confirm, Confirmation::NotRequested);
-            
-            handle.await;
-            let rv = *fibval.lock().await;
-            // really should create a custom error type for this
-            let rv = rv.unwrap();
-            Ok(rv)
+    /// Request the items from the fibonacci series at each of the supplied indices,
+    /// pipelined over the one reply queue, returning the results in the same order.
+    /// Only a bounded number of the requests are awaiting a reply at any one time.
+    /// Each fails independently, eg with `Error::Overflow` if its value does not
+    /// fit in a u64.
+    pub async fn call_many(&self, inputs: &[u32]) -> Vec<Result<u64>> {
+        stream::iter(inputs)
+            .map(|&input| self.call(input))
+            .buffered(MAX_IN_FLIGHT)
+            .collect()
+            .await
+    }
+
+    /// Request the exact values of the items from the fibonacci series at each of
+    /// the supplied indices, as decimal strings, pipelined over the one reply queue,
+    /// returning the results in the same order.
+    pub async fn call_many_big(&self, inputs: &[u32]) -> Vec<Result<String>> {
+        stream::iter(inputs)
+            .map(|&input| self.call_big(input))
+            .buffered(MAX_IN_FLIGHT)
+            .collect()
+            .await
+    }
+
+    /// Cancel the reply consumer and delete the reply queue. Any requests
+    /// still waiting on a reply fail with `Error::Disconnected`
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `FibClient::shutdown` instead.
+    pub fn close(self) -> Result<()> {
+        task::block_on(self.shutdown())
+    }
+
+    /// Cancel the reply consumer and delete the reply queue. Any requests
+    /// still waiting on a reply fail with `Error::Disconnected`
+    pub async fn shutdown(self) -> Result<()> {
+        self.channel.shutdown().await
+    }
+
+    // publish the request and wait for the reply, optionally bounded by timeout.
+    // The reply is the decimal representation of the value.
+    async fn request(&self, input: u32, timeout: Option<Duration>) -> Result<String> {
+        let properties = BasicProperties::default()
+            .with_content_type(ampt::ShortString::from("text/plain"));
+        let data = self.channel
+            .request(QUEUE, input.to_string().into_bytes(), properties, timeout)
+            .await?;
+
+        let value = String::from_utf8(data)
+            .map_err(|_| Error::MalformedReply("unable to convert reply to string".into()))?;
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::MalformedReply(format!("'{}' is not a decimal number", value)));
+        }
+        info!("[x] received fib({}) = {}", input, value);
+        Ok(value)
+    }
+}
+
+// convert the decimal representation of fib(input) to a u64,
+// reporting values which are too large as an overflow
+fn parse_checked(input: u32, value: &str) -> Result<u64> {
+    value.parse::<u64>().map_err(|_| Error::Overflow(input))
+}
+
+/// A typed client for an arbitrary remote procedure, served on a named queue.
+///
+/// Requests are serialized, and replies deserialized, via serde, using the
+/// supplied `Codec`. The codec is advertised to the server via the `content_type`
+/// property. Like `FibClient`, the client shares a single reply queue across all
+/// of its requests.
+pub struct RpcClient<Req, Resp> {
+    channel: ReplyChannel,
+    queue_name: String,
+    codec: Codec,
+    timeout: Option<Duration>,
+    _marker: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req, Resp> RpcClient<Req, Resp>
+where
+    Req: Serialize,
+    Resp: DeserializeOwned,
+{
+    /// Create a new instance of RpcClient, which sends requests to the named queue
+    /// encoded via the supplied codec.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `RpcClient::connect` instead.
+    pub fn new(queue_name: impl Into<String>, codec: Codec) -> Result<Self> {
+        task::block_on(Self::connect(queue_name, codec))
+    }
+
+    /// Connect to the RabbitMq server described by the `AMQP_*` environment
+    /// variables (see `ConnectionConfig::from_env`), and create a new instance of RpcClient, which
+    /// sends requests to the named queue encoded via the supplied codec.
+    pub async fn connect(queue_name: impl Into<String>, codec: Codec) -> Result<Self> {
+        let inner = SimpleClient::connect(ConnectionConfig::from_env()?).await?;
+        Self::from_client(inner, queue_name, codec).await
+    }
+
+    /// Create a new instance of RpcClient from an existing SimpleClient
+    pub async fn from_client(inner: SimpleClient, queue_name: impl Into<String>, codec: Codec) -> Result<Self> {
+        let channel = ReplyChannel::setup(inner).await?;
+        Ok(Self{
+            channel,
+            queue_name: queue_name.into(),
+            codec,
+            timeout: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Set the default timeout used by `call`. If None is supplied,
+    /// `call` will wait for a reply indefinitely
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Retrieve the default timeout
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set the backoff used to reconnect when the connection to RabbitMq is lost.
+    /// By default, up to 5 attempts are made. If None is supplied, requests fail
+    /// with `Error::Disconnected` instead.
+    pub fn set_reconnect(&mut self, backoff: Option<Backoff>) {
+        self.channel.reconnect = backoff;
+    }
+
+    /// Retrieve the backoff used to reconnect, if the client reconnects
+    pub fn reconnect(&self) -> Option<&Backoff> {
+        self.channel.reconnect.as_ref()
+    }
+
+    /// Retrieve the name of the queue requests are sent to
+    pub fn queue_name(&self) -> &str {
+        &self.queue_name
+    }
+
+    /// Retrieve the codec used to encode requests and decode replies
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Call the remote procedure, waiting at most as long as the default timeout,
+    /// if one has been set.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `RpcClient::call` instead.
+    pub fn call_blocking(&self, request: &Req) -> Result<Resp> {
+        task::block_on(self.call(request))
+    }
+
+    /// Call the remote procedure, waiting at most as long as the default timeout,
+    /// if one has been set.
+    pub async fn call(&self, request: &Req) -> Result<Resp> {
+        self.request(request, self.timeout).await
+    }
+
+    /// Call the remote procedure, giving up with `Error::Timeout` if no reply
+    /// arrives within `timeout`.
+    pub async fn call_with_timeout(&self, request: &Req, timeout: Duration) -> Result<Resp> {
+        self.request(request, Some(timeout)).await
+    }
+
+    /// Cancel the reply consumer and delete the reply queue. Any requests
+    /// still waiting on a reply fail with `Error::Disconnected`
+    pub async fn shutdown(self) -> Result<()> {
+        self.channel.shutdown().await
+    }
+
+    // encode the request, publish it, and decode the reply
+    async fn request(&self, request: &Req, timeout: Option<Duration>) -> Result<Resp> {
+        self.channel
+            .typed_request(&self.queue_name, self.codec, None, request, timeout)
+            .await
+    }
+}
+
+/// A client for a server offering several named methods on one queue (see `Router`).
+///
+/// The method is named via the `type` property of the request. Arguments are
+/// serialized, and replies deserialized, via serde, using the supplied `Codec`.
+/// Calling a method the server does not provide fails with `Error::MethodNotFound`.
+pub struct MethodClient {
+    channel: ReplyChannel,
+    queue_name: String,
+    codec: Codec,
+    timeout: Option<Duration>,
+}
+
+impl MethodClient {
+    /// Create a new instance of MethodClient, which sends requests to the named queue
+    /// encoded via the supplied codec.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `MethodClient::connect` instead.
+    pub fn new(queue_name: impl Into<String>, codec: Codec) -> Result<Self> {
+        task::block_on(Self::connect(queue_name, codec))
+    }
+
+    /// Connect to the RabbitMq server described by the `AMQP_*` environment
+    /// variables (see `ConnectionConfig::from_env`), and create a new instance of MethodClient, which
+    /// sends requests to the named queue encoded via the supplied codec.
+    pub async fn connect(queue_name: impl Into<String>, codec: Codec) -> Result<Self> {
+        let inner = SimpleClient::connect(ConnectionConfig::from_env()?).await?;
+        Self::from_client(inner, queue_name, codec).await
+    }
+
+    /// Create a new instance of MethodClient from an existing SimpleClient
+    pub async fn from_client(inner: SimpleClient, queue_name: impl Into<String>, codec: Codec) -> Result<Self> {
+        let channel = ReplyChannel::setup(inner).await?;
+        Ok(Self{
+            channel,
+            queue_name: queue_name.into(),
+            codec,
+            timeout: None,
         })
     }
+
+    /// Set the default timeout used by `call`. If None is supplied,
+    /// `call` will wait for a reply indefinitely
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Retrieve the default timeout
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set the backoff used to reconnect when the connection to RabbitMq is lost.
+    /// By default, up to 5 attempts are made. If None is supplied, requests fail
+    /// with `Error::Disconnected` instead.
+    pub fn set_reconnect(&mut self, backoff: Option<Backoff>) {
+        self.channel.reconnect = backoff;
+    }
+
+    /// Retrieve the backoff used to reconnect, if the client reconnects
+    pub fn reconnect(&self) -> Option<&Backoff> {
+        self.channel.reconnect.as_ref()
+    }
+
+    /// Retrieve the name of the queue requests are sent to
+    pub fn queue_name(&self) -> &str {
+        &self.queue_name
+    }
+
+    /// Retrieve the codec used to encode requests and decode replies
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Call the named method with the supplied arguments, waiting at most as long as
+    /// the default timeout, if one has been set.
+    ///
+    /// This blocks the current thread. From within async code, use
+    /// `MethodClient::call` instead.
+    pub fn call_blocking<Req, Resp>(&self, method: &str, args: &Req) -> Result<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        task::block_on(self.call(method, args))
+    }
+
+    /// Call the named method with the supplied arguments, waiting at most as long as
+    /// the default timeout, if one has been set.
+    pub async fn call<Req, Resp>(&self, method: &str, args: &Req) -> Result<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        self.channel
+            .typed_request(&self.queue_name, self.codec, Some(method), args, self.timeout)
+            .await
+    }
+
+    /// Call the named method with the supplied arguments, giving up with
+    /// `Error::Timeout` if no reply arrives within `timeout`.
+    pub async fn call_with_timeout<Req, Resp>(&self, method: &str, args: &Req, timeout: Duration) -> Result<Resp>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        self.channel
+            .typed_request(&self.queue_name, self.codec, Some(method), args, Some(timeout))
+            .await
+    }
+
+    /// Cancel the reply consumer and delete the reply queue. Any requests
+    /// still waiting on a reply fail with `Error::Disconnected`
+    pub async fn shutdown(self) -> Result<()> {
+        self.channel.shutdown().await
+    }
+}
+
+// route each reply arriving on the reply queue to the caller waiting on its
+// correlation id, flagging the session as closed once the consumer goes away.
+async fn dispatch(mut consumer: Consumer, pending: Pending, closed: Arc<AtomicBool>) {
+    while let Some(delivery_result) = consumer.next().await {
+        let delivery = match delivery_result {
+            Ok((_channel, delivery)) => delivery,
+            Err(err) => {
+                error!("reply consumer failed: {}", err);
+                break;
+            }
+        };
+        let cid = match delivery.properties.correlation_id() {
+            Some(cid) => cid.as_str().to_string(),
+            None => {
+                warn!("dropping reply without a correlation id");
+                continue;
+            }
+        };
+        let reply = reply_result(&delivery.properties, delivery.data);
+        match pending.lock().await.remove(&cid) {
+            // the receiver may have given up already, which is fine
+            Some(sender) => { let _ = sender.send(reply); },
+            None => debug!("dropping reply for unknown or abandoned request {}", cid),
+        }
+    }
+    closed.store(true, Ordering::SeqCst);
+    // dropping the senders wakes any callers still waiting
+    pending.lock().await.clear();
+    info!("reply consumer finished");
 }
@@ -1,78 +1,262 @@
+use async_std::sync::Arc;
 use async_std::task;
 use lapin::{
     BasicProperties,
-    options::*, 
-    Result as AsyncResult,
-    types::FieldTable, 
+    Channel,
+    message::Delivery,
+    options::*,
+    types::{FieldTable, ShortString},
 };
-use std::iter::Iterator;
-use tracing::{info};
+use futures::{future::{self, Either}, Future, Stream, StreamExt};
+use std::fmt;
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, error, warn};
 
-use crate::{SimpleClient, fib};
+use crate::{SimpleClient, ConnectionConfig, Error, Result};
+use crate::handler::{Handler, Request, FibHandler};
+use crate::protocol::{is_expired, status_headers, status_of};
+use crate::cache::{CacheKey, CacheStats, ResultCache};
+use crate::pool::WorkerPool;
+use crate::reconnect::{connect_with_backoff, Backoff};
+use crate::server_builder::RpcServerBuilder;
+use crate::shutdown::{self, ShutdownHandle, ShutdownSignal};
 
+// how long a session must have run for, if it handled no requests, to count as
+// having got going, so that losing it does not add to the backoff
+const STABLE_SESSION: Duration = Duration::from_secs(60);
 
-/// Server which receives messages over RabbitMq which each provide an
-/// index into fibonacci sequence, which the service calculates and
-/// returns over a reply channel provided in the message.
-pub struct FibRpcServer {
+
+/// Server which receives requests over RabbitMq, hands each of them to
+/// a Handler, and returns the Handler's response over a reply channel
+/// provided in the message.
+pub struct RpcServer<H: Handler> {
     queue_name: String,
     msgcnt: Option<u16>,
-    inner: SimpleClient,
+    inner: RwLock<Arc<SimpleClient>>,
     queue_declare_opts: QueueDeclareOptions,
     qos_opts: BasicQosOptions,
-    consume_opts: BasicConsumeOptions
+    consume_opts: BasicConsumeOptions,
+    consumer_tag: String,
+    handler: Arc<H>,
+    cache: Option<Arc<ResultCache>>,
+    reconnect: Option<Backoff>,
+    shutdown_handle: ShutdownHandle,
+    shutdown_signal: ShutdownSignal,
+}
+
+/// Summary of the requests handled by a RpcServer, returned once it stops serving
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ServeSummary {
+    /// Number of requests which were answered
+    pub processed: u64,
+    /// Number of requests which could not be answered (eg missing a reply_to)
+    pub failed: u64,
+    /// Number of requests which were skipped, as their deadline had passed
+    pub expired: u64,
+}
+
+impl ServeSummary {
+    /// Total number of requests received
+    pub fn total(&self) -> u64 {
+        self.processed + self.failed + self.expired
+    }
+
+    fn merge(&mut self, other: &ServeSummary) {
+        self.processed += other.processed;
+        self.failed += other.failed;
+        self.expired += other.expired;
+    }
+
+    fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Processed => self.processed += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Expired => self.expired += 1,
+        }
+    }
 }
 
-impl FibRpcServer {
-    /// Create a new instance of the FibRpcServer, given a queue name, optional message count,
-    /// and various lapin options
+impl fmt::Display for ServeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "processed {} requests ({} failed, {} expired)",
+            self.processed, self.failed, self.expired
+        )
+    }
+}
+
+// what became of a single request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Processed,
+    Failed,
+    Expired,
+}
+
+// why a session stopped consuming requests
+#[derive(Debug)]
+enum SessionEnd {
+    Shutdown,
+    Cancelled,
+    Failed(lapin::Error),
+}
+
+/// Server which receives messages over RabbitMq which each provide an
+/// index into fibonacci sequence, which the service calculates and
+/// returns over a reply channel provided in the message.
+pub type FibRpcServer = RpcServer<FibHandler>;
+
+impl<H: Handler + Default> RpcServer<H> {
+    /// Create a builder for a RpcServer serving the default handler. See
+    /// `RpcServerBuilder`.
+    pub fn builder() -> RpcServerBuilder<H> {
+        RpcServerBuilder::new()
+    }
+
+    /// Create a new instance of the RpcServer, given a queue name, optional message count,
+    /// and various lapin options. `RpcServer::builder` offers named settings instead.
+    ///
+    /// This blocks the current thread while connecting. From within async code, use
+    /// `RpcServer::from_client` or `RpcServer::connect` instead.
     pub fn new(
         queue_name: impl Into<String>,
         msgcnt: Option<u16>,
         queue_declare_opts: QueueDeclareOptions,
         qos_opts: BasicQosOptions,
         consume_opts: BasicConsumeOptions
-    ) -> AsyncResult<Self> {
+    ) -> Result<Self> {
         let client = SimpleClient::new()?;
+        Ok(Self::from_client(
+            client,
+            queue_name,
+            msgcnt,
+            queue_declare_opts,
+            qos_opts,
+            consume_opts,
+            H::default(),
+        ))
+    }
+
+    /// Create  a RpcServer instance with default values for Optionals.
+    ///
+    /// This cannot be implemented via Default trait, as it is fallible.
+    ///
+    /// This blocks the current thread while connecting. From within async code, use
+    /// `RpcServer::connect` instead.
+    pub fn with_defaults(queue_name: impl Into<String>) -> Result<Self> {
+        Self::with_handler(queue_name, H::default())
+    }
+
+    /// Connect to the RabbitMq server described by the `AMQP_*` environment
+    /// variables (see `ConnectionConfig::from_env`), and create a RpcServer instance with default
+    /// values for Optionals.
+    pub async fn connect(queue_name: impl Into<String>) -> Result<Self> {
+        Self::connect_with_handler(queue_name, H::default()).await
+    }
+}
+
+impl<H: Handler> RpcServer<H> {
+    /// Create a new instance of the RpcServer from an existing SimpleClient, given
+    /// a queue name, optional message count, various lapin options, and the handler
+    /// which will process requests
+    pub fn from_client(
+        client: SimpleClient,
+        queue_name: impl Into<String>,
+        msgcnt: Option<u16>,
+        queue_declare_opts: QueueDeclareOptions,
+        qos_opts: BasicQosOptions,
+        consume_opts: BasicConsumeOptions,
+        handler: H,
+    ) -> Self {
         let name = queue_name.into();
-        Ok(FibRpcServer {
+        let (shutdown_handle, shutdown_signal) = shutdown::channel();
+        RpcServer {
             queue_name: name,
             msgcnt,
-            inner: client,
+            inner: RwLock::new(Arc::new(client)),
             queue_declare_opts,
             qos_opts,
-            consume_opts
-        })
+            consume_opts,
+            consumer_tag: String::new(),
+            handler: Arc::new(handler),
+            cache: None,
+            reconnect: Some(Backoff::default()),
+            shutdown_handle,
+            shutdown_signal,
+        }
     }
 
-    /// Create  a FibRpcServer instance with default values for Optionals. 
+    /// Create a RpcServer instance serving the supplied handler, with default values
+    /// for Optionals.
     ///
-    /// This cannot be implemented via Default trait, as it is fallible.
-    pub fn with_defaults(queue_name: impl Into<String>) -> AsyncResult<Self> {
-        Self::new(
+    /// This blocks the current thread while connecting. From within async code, use
+    /// `RpcServer::connect_with_handler` instead.
+    pub fn with_handler(queue_name: impl Into<String>, handler: H) -> Result<Self> {
+        task::block_on(Self::connect_with_handler(queue_name, handler))
+    }
+
+    /// Connect to the RabbitMq server described by the `AMQP_*` environment
+    /// variables (see `ConnectionConfig::from_env`), and create a RpcServer instance serving the
+    /// supplied handler, with default values for Optionals.
+    pub async fn connect_with_handler(queue_name: impl Into<String>, handler: H) -> Result<Self> {
+        let client = SimpleClient::connect(ConnectionConfig::from_env()?).await?;
+        Ok(Self::from_client(
+            client,
             queue_name,
             None,
             QueueDeclareOptions{
                 durable: false,
                 exclusive:false,
-                auto_delete: false, 
+                auto_delete: false,
                 nowait: false,
                 ..Default::default()
             },
-            BasicQosOptions{global: false, ..Default::default()},
+            BasicQosOptions{global: false},
             BasicConsumeOptions{
                 no_local: false,
                 no_ack: false,
                 exclusive: false,
                 nowait: false
             },
-        )
+            handler,
+        ))
     }
 
-    /// retrieve a reference to the inner SimpleClient instance, which houses
-    /// the connection and channel
-    pub fn client(&self) -> &SimpleClient {
-        &self.inner
+    /// retrieve the current SimpleClient instance, which houses the connection
+    /// and channel. It is replaced whenever the server reconnects.
+    pub fn client(&self) -> Arc<SimpleClient> {
+        self.inner.read().unwrap().clone()
+    }
+    /// Retrieve a reference to the handler which processes requests
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+    /// Retrieve a handle which may be used to stop the server, from another task,
+    /// thread, or signal handler. Once triggered, the server stops consuming, lets
+    /// the requests in flight finish, and closes its channel and connection.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown_handle.clone()
+    }
+    /// Cache the replies to up to `size` distinct requests, answering repeats of
+    /// them without calling the handler. Consumes and returns the server, so that
+    /// it may be chained after construction.
+    pub fn with_cache_size(mut self, size: usize) -> Self {
+        self.set_cache_size(Some(size));
+        self
+    }
+    /// Set the number of replies cached. If None (or 0) is supplied, replies are
+    /// not cached. Changing the size discards any cached replies.
+    pub fn set_cache_size(&mut self, size: Option<usize>) {
+        self.cache = match size {
+            Some(size) if size > 0 => Some(Arc::new(ResultCache::new(size))),
+            _ => None,
+        };
+    }
+    /// Retrieve the hit and miss counts of the reply cache, if replies are cached
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
     }
     /// Set the queue name after  the fact.
     pub fn set_queue_name(&mut self, name: impl Into<String>) {
@@ -83,7 +267,7 @@ impl FibRpcServer {
         &self.queue_name
     }
     /// Set the total number of messages which may be processed
-    /// at once. If None is supplied, there are no limits
+    /// at once. If None is supplied, messages are processed one at a time
     pub fn set_message_count(&mut self, cnt: Option<u16>) {
         self.msgcnt = cnt
     }
@@ -103,7 +287,7 @@ impl FibRpcServer {
     pub fn set_qos_options(&mut self, options: BasicQosOptions) {
         self.qos_opts = options;
     }
-    /// Retrieve a reference to the BasicQosOptions 
+    /// Retrieve a reference to the BasicQosOptions
     pub fn qos_options(&self)-> &BasicQosOptions {
         &self.qos_opts
     }
@@ -115,85 +299,381 @@ impl FibRpcServer {
     pub fn consume_opts(&self) -> &BasicConsumeOptions {
         &self.consume_opts
     }
-    /// Start the service up. This method will block until done
-    pub fn serve(&self) -> AsyncResult<()> {
-        task::block_on(async {
-
-            let queue = self.client().chan
-                    .queue_declare(
-                        self.queue_name.as_str(),
-                        self.queue_declare_opts.clone(),
-                        FieldTable::default(),
-                    )
-                    .await?;
-
-            info!(?queue, "Declared queue '{}'", &queue.name().as_str());
+    /// Set the tag identifying the server's consumer to the broker. If empty
+    /// (the default), the broker generates one.
+    pub fn set_consumer_tag(&mut self, tag: impl Into<String>) {
+        self.consumer_tag = tag.into();
+    }
+    /// Retrieve the consumer tag
+    pub fn consumer_tag(&self) -> &str {
+        &self.consumer_tag
+    }
+    /// Set the backoff used to reconnect when the connection to RabbitMq is lost.
+    /// If None is supplied, the server stops serving instead.
+    pub fn set_reconnect(&mut self, backoff: Option<Backoff>) {
+        self.reconnect = backoff;
+    }
+    /// Retrieve the backoff used to reconnect, if the server reconnects
+    pub fn reconnect(&self) -> Option<&Backoff> {
+        self.reconnect.as_ref()
+    }
+    /// Start the service up. This method will block until the server is shut down
+    /// (see `RpcServer::shutdown_handle`), or the connection is lost and cannot be
+    /// reestablished.
+    ///
+    /// From within async code, use `RpcServer::run` instead.
+    pub fn serve(&self) -> Result<ServeSummary> {
+        task::block_on(self.run())
+    }
 
-            // Set the number of messages that the channel's consumer can process
-            // at one time
+    /// Start the service up, completing when the server is shut down, the broker
+    /// cancels its consumer, or the connection is lost and cannot be reestablished,
+    /// with a summary of the requests handled.
+    ///
+    /// When the connection (or channel) is lost, eg because the broker restarted,
+    /// the server closes what is left of it, reconnects with exponential backoff,
+    /// then redeclares its queue, reapplies its QoS, and consumes again. If it cannot
+    /// reconnect, or reconnecting is disabled, it fails with `Error::ConnectionLost`,
+    /// which carries the summary of the requests handled until then.
+    pub async fn run(&self) -> Result<ServeSummary> {
+        let mut summary = ServeSummary::default();
+        let mut session = 0u32;
+        // sessions in a row which were lost before they got going
+        let mut failures = 0u32;
+        loop {
+            let client = self.client();
+            let started = Instant::now();
+            let mut handled = 0;
+            let lost = match self.run_session(&client).await {
+                Ok((session_summary, consumer_tag, end)) => {
+                    handled = session_summary.total();
+                    summary.merge(&session_summary);
+                    match end {
+                        SessionEnd::Shutdown => {
+                            // any prefetched requests which were not handed to us are returned
+                            // to the queue by the broker once the channel closes
+                            client.chan
+                                .basic_cancel(consumer_tag.as_str(), BasicCancelOptions::default())
+                                .await?;
+                            client.chan.close(200, "server shutting down").await?;
+                            client.conn.close(200, "server shutting down").await?;
+                            info!("closed channel and connection");
+                            break;
+                        }
+                        // the broker cancelled the consumer (eg the queue was deleted)
+                        // over a healthy channel, so there is nothing to recover
+                        SessionEnd::Cancelled if client.chan.status().connected() => {
+                            warn!(session, "consumer cancelled by the broker, stopping");
+                            client.chan.close(200, "consumer cancelled").await?;
+                            client.conn.close(200, "consumer cancelled").await?;
+                            info!("closed channel and connection");
+                            break;
+                        }
+                        SessionEnd::Cancelled => Error::Disconnected,
+                        SessionEnd::Failed(err) => err.into(),
+                    }
+                }
+                // a server which cannot get started reports why, rather than retrying
+                Err(err) if session == 0 => return Err(err),
+                Err(err) => err,
+            };
 
-            //let qos_options = BasicQosOptions{global: false, ..Default::default()};
-            let qos_options = self.qos_opts.clone();
-            if let Some(msgcnt) = self.msgcnt {
-                self.client().chan.basic_qos(msgcnt, qos_options).await?;
-            } else {
-                self.client().chan.basic_qos(1, qos_options).await?;
+            // only the channel or consumer may have died, so close the connection
+            // rather than leak it. If it is already gone, there is nothing to close.
+            if client.conn.status().connected() {
+                if let Err(err) = client.conn.close(200, "reconnecting").await {
+                    warn!(session, error = %err, "unable to close the lost connection");
+                }
             }
-            info!("QOS OPTIONS: {:#?}", qos_options);
+            let backoff = match &self.reconnect {
+                Some(backoff) => backoff,
+                None => {
+                    warn!(session, error = %lost, "connection lost, not reconnecting");
+                    info!("{}", summary);
+                    return Err(Error::ConnectionLost{summary, source: Box::new(lost)});
+                }
+            };
+            // a session which is lost straight after reconnecting (eg because the
+            // queue cannot be declared) is retried after a growing delay, rather
+            // than at once, until the backoff gives up
+            let delay = if handled > 0 || started.elapsed() >= STABLE_SESSION {
+                failures = 0;
+                Duration::from_secs(0)
+            } else {
+                match backoff.delay(failures) {
+                    Some(delay) => {
+                        failures += 1;
+                        delay
+                    }
+                    None => {
+                        warn!(session, error = %lost, "connection lost again straight away, giving up");
+                        info!("{}", summary);
+                        return Err(Error::ConnectionLost{summary, source: Box::new(lost)});
+                    }
+                }
+            };
+            warn!(session, ?delay, error = %lost, "connection lost, reconnecting");
+            let reconnect = Box::pin(async {
+                task::sleep(delay).await;
+                connect_with_backoff(client.config(), backoff).await
+            });
+            let shutdown = Box::pin(self.shutdown_signal.wait());
+            let client = match future::select(reconnect, shutdown).await {
+                Either::Left((Ok(client), _)) => client,
+                Either::Left((Err(err), _)) => {
+                    info!("{}", summary);
+                    return Err(Error::ConnectionLost{summary, source: Box::new(err)});
+                }
+                Either::Right(_) => {
+                    info!("shutdown requested while reconnecting");
+                    break;
+                }
+            };
+            *self.inner.write().unwrap() = Arc::new(client);
+            session += 1;
+            info!(session, "reconnected, resuming");
+        }
+        info!("{}", summary);
+
+        Ok(summary)
+    }
 
-            // Create the consumer for the incoming. named queue
-            let  consumer = self.client().chan
-                .basic_consume(
+    // declare the queue, set the QoS, and consume requests over the supplied
+    // client until shut down, or the consumer ends, returning a summary of the
+    // requests handled, along with the consumer tag and how the session ended.
+    async fn run_session(&self, client: &SimpleClient) -> Result<(ServeSummary, ShortString, SessionEnd)> {
+        let queue = client.chan
+                .queue_declare(
                     self.queue_name.as_str(),
-                    "",
-                    self.consume_opts.clone(),
+                    self.queue_declare_opts,
                     FieldTable::default(),
                 )
                 .await?;
 
-            info!("Channel Consumer created");
-            // this will automagically move long running jobs onto a separate
-            // thread if they take too long. thanks async_std.
-            let handle = task::spawn(async move {
-            
-                let mut consumer_iter = consumer.into_iter();
-                while let Some(delivery_result) = consumer_iter.next() {
-                    if let Ok((channel, delivery)) = delivery_result {
-                        // perhaps we can use bytevec crate to 
-                        // skip the string
-                        let val = std::str::from_utf8(&delivery.data);
-                        
-                        if let Ok(value) = val {
-                            if let Ok(intvalue) = value.parse::<u32>() {
-                                println!("[.] Calculating fib({})", intvalue);
-                                let result = fib(intvalue as usize);
-                                println!("[X] fib({}) = {}",intvalue, result);
-                                let cid = delivery.properties.correlation_id().clone().unwrap();
-                                channel.basic_publish(
-                                    "", //exchange
-                                    delivery.properties.reply_to().as_ref().unwrap().as_str(),
-                                    BasicPublishOptions::default(),
-                                    result.to_string().as_bytes().to_vec(),
-                                    BasicProperties::default().with_correlation_id(cid)
-                                ).await.unwrap();
-                            } else {
-                                println!("ERROR: Unable to convert {} to an int", &value);
-                            }
-                            
-                        } else {
-                            println!("unable to convert raw data from delivery to string");
-                        }
-                        channel
-                            .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+        info!(?queue, "Declared queue '{}'", &queue.name().as_str());
+
+        // Set the number of messages that the channel's consumer can process
+        // at one time
+
+        //let qos_options = BasicQosOptions{global: false, ..Default::default()};
+        let qos_options = self.qos_opts;
+        if let Some(msgcnt) = self.msgcnt {
+            client.chan.basic_qos(msgcnt, qos_options).await?;
+        } else {
+            client.chan.basic_qos(1, qos_options).await?;
+        }
+        info!("QOS OPTIONS: {:#?}", qos_options);
+
+        // Create the consumer for the incoming. named queue
+        let consumer = client.chan
+            .basic_consume(
+                self.queue_name.as_str(),
+                self.consumer_tag.as_str(),
+                self.consume_opts,
+                FieldTable::default(),
+            )
+            .await?;
+        let consumer_tag = consumer.tag();
+
+        info!("Channel Consumer created");
+        let handler = self.handler.clone();
+        let cache = self.cache.clone();
+        let limit = self.msgcnt.unwrap_or(1) as usize;
+        let handle = task::spawn(serve_deliveries(
+            consumer,
+            limit,
+            self.shutdown_signal.clone(),
+            move |(channel, delivery): (Channel, Delivery)| {
+                let handler = handler.clone();
+                let cache = cache.clone();
+                // each request is acked as soon as it is answered, independently
+                // of the others
+                async move {
+                    // nobody is waiting for the reply to a request whose deadline
+                    // has passed, so it is rejected without calling the handler
+                    if is_expired(&delivery.properties, SystemTime::now()) {
+                        info!("skipping expired request {:?}", delivery.properties.correlation_id());
+                        if let Err(err) = channel
+                            .basic_reject(delivery.delivery_tag, BasicRejectOptions { requeue: false })
                             .await
-                            .expect("failed to ack");
+                        {
+                            error!("failed to reject request: {}", err);
+                        }
+                        return Outcome::Expired;
                     }
-                } 
+                    // a bad request is logged and acked rather than allowed
+                    // to take down the server
+                    let outcome = match process(&channel, &delivery, handler, cache).await {
+                        Ok(()) => Outcome::Processed,
+                        Err(err) => {
+                            error!("unable to process request: {}", err);
+                            Outcome::Failed
+                        }
+                    };
+                    if let Err(err) = channel
+                        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                        .await
+                    {
+                        error!("failed to ack request: {}", err);
+                    }
+                    outcome
+                }
+            },
+        ));
 
-            });
-            handle.await;
+        let (summary, end) = handle.await;
+        Ok((summary, consumer_tag, end))
+    }
+}
 
-            Ok(())
-        })
+// hand each delivery to `job`, working on up to `limit` of them at once, until
+// the deliveries run out or fail, or a shutdown is requested. The jobs still in
+// flight are waited for before returning a summary of them, and why it stopped.
+async fn serve_deliveries<S, T, F, Fut>(
+    mut deliveries: S,
+    limit: usize,
+    signal: ShutdownSignal,
+    job: F,
+) -> (ServeSummary, SessionEnd)
+where
+    S: Stream<Item = lapin::Result<T>> + Unpin,
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = Outcome> + Send + 'static,
+{
+    let mut summary = ServeSummary::default();
+    let mut pool = WorkerPool::new(limit);
+    let end = loop {
+        // a shutdown is only noticed between requests, so the requests
+        // in flight are always answered and acked
+        let shutdown = Box::pin(signal.wait());
+        let delivery_result = match future::select(deliveries.next(), shutdown).await {
+            Either::Left((Some(delivery_result), _)) => delivery_result,
+            Either::Left((None, _)) => {
+                warn!("consumer cancelled");
+                break SessionEnd::Cancelled;
+            }
+            Either::Right(_) => {
+                info!("shutdown requested");
+                break SessionEnd::Shutdown;
+            }
+        };
+        let delivery = match delivery_result {
+            Ok(delivery) => delivery,
+            Err(err) => {
+                error!("consumer failed: {}", err);
+                break SessionEnd::Failed(err);
+            }
+        };
+        for outcome in pool.spawn(job(delivery)).await {
+            summary.record(outcome);
+        }
+    };
+    for outcome in pool.drain().await {
+        summary.record(outcome);
     }
-}
\ No newline at end of file
+    (summary, end)
+}
+
+// hand the request to the handler, and publish its response to the delivery's
+// reply_to queue. If the handler fails, an error reply is published instead, so
+// that the client is not left waiting. Successful replies are cached, if there
+// is a cache, and repeated requests are answered from it.
+async fn process<H: Handler>(
+    channel: &Channel,
+    delivery: &Delivery,
+    handler: Arc<H>,
+    cache: Option<Arc<ResultCache>>,
+) -> Result<()> {
+    let reply_to = delivery.properties.reply_to().as_ref().ok_or(Error::MissingReplyTo)?;
+    let cid = delivery.properties.correlation_id().clone().ok_or(Error::MissingCorrelationId)?;
+
+    let cached = cache.map(|cache| {
+        let key = CacheKey::new(
+            delivery.properties.kind().as_ref().map(|kind| kind.as_str()),
+            delivery.properties.content_type().as_ref().map(|ct| ct.as_str()),
+            &delivery.data,
+        );
+        let hit = cache.get(&key);
+        (cache, key, hit)
+    });
+    let outcome = match cached {
+        Some((_, _, Some(payload))) => Ok(payload),
+        cached => {
+            // handlers are synchronous, and may be slow, so they are run on a thread
+            // where they will not hold up the other requests
+            let data = delivery.data.clone();
+            let properties = delivery.properties.clone();
+            let outcome = task::spawn_blocking(move || {
+                let request = Request {
+                    data: &data,
+                    properties: &properties,
+                };
+                handler.handle(&request)
+            })
+            .await;
+            if let (Some((cache, key, _)), Ok(payload)) = (cached, &outcome) {
+                cache.put(key, payload.clone());
+            }
+            outcome
+        }
+    };
+    let status = status_of(&outcome);
+    let payload = match outcome {
+        Ok(payload) => payload,
+        Err(Error::MethodNotFound(name)) => {
+            error!("rejecting request for unknown method: {}", name);
+            name.into_bytes()
+        }
+        Err(err) => {
+            error!("rejecting request: {}", err);
+            err.to_string().into_bytes()
+        }
+    };
+
+    let mut properties = BasicProperties::default()
+        .with_correlation_id(cid)
+        .with_headers(status_headers(status));
+    // reply in kind, so that typed clients can decode the reply
+    if let Some(content_type) = delivery.properties.content_type() {
+        properties = properties.with_content_type(content_type.clone());
+    }
+
+    channel.basic_publish(
+        "", //exchange
+        reply_to.as_str(),
+        BasicPublishOptions::default(),
+        payload,
+        properties
+    ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::{future::timeout, sync::Barrier};
+    use futures::stream;
+    use std::time::Duration;
+
+    #[test]
+    fn deliveries_are_served_concurrently() {
+        const LIMIT: usize = 3;
+        const WAIT: Duration = Duration::from_secs(5);
+        // every job waits for all of the others to start, so served one at a
+        // time they would each time out
+        let barrier = Arc::new(Barrier::new(LIMIT));
+        let deliveries = stream::iter((0..LIMIT).map(Ok::<_, lapin::Error>));
+        let (_handle, signal) = shutdown::channel();
+        let (summary, end) = task::block_on(serve_deliveries(deliveries, LIMIT, signal, |_| {
+            let barrier = barrier.clone();
+            async move {
+                match timeout(WAIT, barrier.wait()).await {
+                    Ok(_) => Outcome::Processed,
+                    Err(_) => Outcome::Failed,
+                }
+            }
+        }));
+        assert_eq!(summary, ServeSummary { processed: LIMIT as u64, ..Default::default() });
+        assert!(matches!(end, SessionEnd::Cancelled));
+    }
+}
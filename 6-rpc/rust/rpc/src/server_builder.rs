@@ -0,0 +1,327 @@
+//! server_builder
+//!
+//! # Server Builder
+//! Configure a RpcServer via named settings, rather than positional lapin option
+//! structs. Settings which are not supplied take the same defaults as
+//! `RpcServer::with_defaults`.
+//!
+//! ```no_run
+//! use rpc::{FibRpcServerBuilder, QUEUE};
+//!
+//! let server = FibRpcServerBuilder::new()
+//!     .queue_name(QUEUE)
+//!     .prefetch(4)
+//!     .durable(true)
+//!     .amqp_addr("amqp://127.0.0.1:5672/%2f")
+//!     .build()?;
+//! let summary = server.serve()?;
+//! # Ok::<(), rpc::Error>(())
+//! ```
+use async_std::task;
+use std::env;
+use lapin::options::*;
+
+use crate::{ConnectionConfig, Error, Result, SimpleClient, QUEUE};
+use crate::handler::{Handler, FibHandler};
+use crate::reconnect::Backoff;
+use crate::rpc_server::RpcServer;
+
+// the longest name the AMQP protocol allows for a queue or consumer tag
+const MAX_NAME_LEN: usize = 255;
+
+/// Builder for a RpcServer
+#[derive(Debug, Clone)]
+pub struct RpcServerBuilder<H> {
+    queue_name: String,
+    prefetch: Option<u16>,
+    durable: bool,
+    exclusive: bool,
+    auto_delete: bool,
+    consumer_tag: String,
+    connection: Option<ConnectionConfig>,
+    addr: Option<String>,
+    cache_size: Option<usize>,
+    reconnect: Option<Backoff>,
+    handler: H,
+}
+
+/// Builder for a FibRpcServer
+pub type FibRpcServerBuilder = RpcServerBuilder<FibHandler>;
+
+impl<H: Handler + Default> RpcServerBuilder<H> {
+    /// Create a new builder for a server serving the default handler on `QUEUE`
+    pub fn new() -> Self {
+        Self::with_handler(H::default())
+    }
+}
+
+impl<H: Handler + Default> Default for RpcServerBuilder<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Handler> RpcServerBuilder<H> {
+    /// Create a new builder for a server serving the supplied handler on `QUEUE`
+    pub fn with_handler(handler: H) -> Self {
+        Self {
+            queue_name: QUEUE.to_string(),
+            prefetch: None,
+            durable: false,
+            exclusive: false,
+            auto_delete: false,
+            consumer_tag: String::new(),
+            connection: None,
+            addr: None,
+            cache_size: None,
+            reconnect: Some(Backoff::default()),
+            handler,
+        }
+    }
+
+    /// Set the name of the queue the server consumes requests from
+    pub fn queue_name(mut self, name: impl Into<String>) -> Self {
+        self.queue_name = name.into();
+        self
+    }
+
+    /// Set the number of requests which may be processed at once (the prefetch
+    /// count). By default, requests are processed one at a time.
+    pub fn prefetch(mut self, count: u16) -> Self {
+        self.prefetch = Some(count);
+        self
+    }
+
+    /// Should the queue survive a restart of the broker?
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Should the queue be restricted to the server's connection, and deleted
+    /// along with it?
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Should the queue be deleted once its last consumer goes away?
+    pub fn auto_delete(mut self, auto_delete: bool) -> Self {
+        self.auto_delete = auto_delete;
+        self
+    }
+
+    /// Set the tag identifying the server's consumer to the broker. By default,
+    /// the broker generates one.
+    pub fn consumer_tag(mut self, tag: impl Into<String>) -> Self {
+        self.consumer_tag = tag.into();
+        self
+    }
+
+    /// Set the address of the RabbitMq server. By default, the connection is
+    /// described by the `AMQP_*` environment variables (see
+    /// `ConnectionConfig::from_env`), falling back to `LOCALHOST`. Only the
+    /// address is replaced: the credentials and other settings are still taken
+    /// from the environment (or the config supplied via `connection`).
+    pub fn amqp_addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = Some(addr.into());
+        self
+    }
+
+    /// Set the config used to connect to the RabbitMq server, in place of the
+    /// `AMQP_*` environment variables
+    pub fn connection(mut self, config: ConnectionConfig) -> Self {
+        self.connection = Some(config);
+        self
+    }
+
+    /// Cache the replies to up to `size` distinct requests. By default, replies
+    /// are not cached.
+    pub fn cache_size(mut self, size: usize) -> Self {
+        self.cache_size = Some(size);
+        self
+    }
+
+    /// Set the backoff used to reconnect when the connection to RabbitMq is lost.
+    /// By default, `Backoff::default()` is used.
+    pub fn reconnect(mut self, backoff: Backoff) -> Self {
+        self.reconnect = Some(backoff);
+        self
+    }
+
+    /// Stop serving when the connection to RabbitMq is lost, rather than
+    /// reconnecting
+    pub fn no_reconnect(mut self) -> Self {
+        self.reconnect = None;
+        self
+    }
+
+    /// Set the handler which processes requests
+    pub fn handler<H2: Handler>(self, handler: H2) -> RpcServerBuilder<H2> {
+        RpcServerBuilder {
+            queue_name: self.queue_name,
+            prefetch: self.prefetch,
+            durable: self.durable,
+            exclusive: self.exclusive,
+            auto_delete: self.auto_delete,
+            consumer_tag: self.consumer_tag,
+            connection: self.connection,
+            addr: self.addr,
+            cache_size: self.cache_size,
+            reconnect: self.reconnect,
+            handler,
+        }
+    }
+
+    /// Check the settings, without connecting
+    pub fn validate(&self) -> Result<()> {
+        if self.queue_name.is_empty() {
+            return Err(Error::Config("the queue name may not be empty".into()));
+        }
+        if self.queue_name.len() > MAX_NAME_LEN {
+            return Err(Error::Config(format!(
+                "the queue name may not exceed {} bytes", MAX_NAME_LEN
+            )));
+        }
+        if self.consumer_tag.len() > MAX_NAME_LEN {
+            return Err(Error::Config(format!(
+                "the consumer tag may not exceed {} bytes", MAX_NAME_LEN
+            )));
+        }
+        if self.prefetch == Some(0) {
+            return Err(Error::Config("the prefetch count must be at least 1".into()));
+        }
+        if let Some(backoff) = &self.reconnect {
+            if backoff.multiplier == 0 || backoff.initial > backoff.max {
+                return Err(Error::Config(
+                    "the reconnect backoff must grow from its initial delay to its max".into()
+                ));
+            }
+        }
+        if self.cache_size == Some(0) {
+            return Err(Error::Config("the cache size must be at least 1".into()));
+        }
+        let uri = self.addr.as_ref().or_else(|| self.connection.as_ref().map(|config| &config.uri));
+        if let Some(uri) = uri {
+            if !uri.starts_with("amqp://") && !uri.starts_with("amqps://") {
+                return Err(Error::Config(format!(
+                    "'{}' is not an amqp:// or amqps:// address", uri
+                )));
+            }
+        }
+        if let Some(config) = &self.connection {
+            config.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Validate the settings, connect, and create the RpcServer
+    ///
+    /// This blocks the current thread while connecting. From within async code, use
+    /// `RpcServerBuilder::connect` instead.
+    pub fn build(self) -> Result<RpcServer<H>> {
+        task::block_on(self.connect())
+    }
+
+    /// Validate the settings, connect, and create the RpcServer
+    pub async fn connect(self) -> Result<RpcServer<H>> {
+        self.validate()?;
+        let config = self.connection_config(|name| env::var(name).ok())?;
+        let client = SimpleClient::connect(config).await?;
+        let mut server = RpcServer::from_client(
+            client,
+            self.queue_name,
+            self.prefetch,
+            QueueDeclareOptions {
+                durable: self.durable,
+                exclusive: self.exclusive,
+                auto_delete: self.auto_delete,
+                ..Default::default()
+            },
+            BasicQosOptions { global: false },
+            BasicConsumeOptions {
+                no_local: false,
+                no_ack: false,
+                exclusive: false,
+                nowait: false,
+            },
+            self.handler,
+        );
+        server.set_consumer_tag(self.consumer_tag);
+        server.set_cache_size(self.cache_size);
+        server.set_reconnect(self.reconnect);
+        Ok(server)
+    }
+
+    // the config to connect with: the one supplied, or else that described by the
+    // variables supplied by `lookup`, with the address replaced if one was set
+    fn connection_config(&self, lookup: impl Fn(&str) -> Option<String>) -> Result<ConnectionConfig> {
+        let mut config = match &self.connection {
+            Some(config) => config.clone(),
+            None => ConnectionConfig::from_vars(lookup)?,
+        };
+        if let Some(addr) = &self.addr {
+            config.uri = addr.clone();
+            config.validate()?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(FibRpcServerBuilder::new().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_bad_settings() {
+        let invalid = vec![
+            FibRpcServerBuilder::new().queue_name(""),
+            FibRpcServerBuilder::new().queue_name("q".repeat(256)),
+            FibRpcServerBuilder::new().consumer_tag("t".repeat(256)),
+            FibRpcServerBuilder::new().prefetch(0),
+            FibRpcServerBuilder::new().cache_size(0),
+            FibRpcServerBuilder::new().amqp_addr("localhost:5672"),
+            FibRpcServerBuilder::new().reconnect(Backoff { multiplier: 0, ..Backoff::default() }),
+        ];
+        for builder in invalid {
+            match builder.validate() {
+                Err(Error::Config(_)) => {}
+                other => panic!("expected a config error for {:?}, got {:?}", builder, other),
+            }
+        }
+    }
+
+    #[test]
+    fn validate_accepts_named_settings() {
+        let builder = FibRpcServerBuilder::new()
+            .queue_name("fib")
+            .prefetch(8)
+            .durable(true)
+            .exclusive(true)
+            .consumer_tag("fib-server")
+            .amqp_addr("amqps://rabbit.example.com:5671/%2f")
+            .cache_size(100);
+        assert!(builder.validate().is_ok());
+    }
+
+    #[test]
+    fn amqp_addr_keeps_the_rest_of_the_environment() {
+        let builder = FibRpcServerBuilder::new().amqp_addr("amqps://rabbit.example.com:5671/%2f");
+        let config = builder
+            .connection_config(|name| match name {
+                "AMQP_ADDR" => Some("amqp://elsewhere:5672/%2f".to_string()),
+                "AMQP_USERNAME" => Some("fib".to_string()),
+                "AMQP_HEARTBEAT" => Some("30".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.uri, "amqps://rabbit.example.com:5671/%2f");
+        assert_eq!(config.username.as_deref(), Some("fib"));
+        assert_eq!(config.heartbeat, Some(30));
+    }
+}
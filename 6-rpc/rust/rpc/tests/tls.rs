@@ -0,0 +1,85 @@
+//! Connect over amqps to a TLS terminating stand-in for RabbitMq, which requires
+//! a client certificate signed by the test CA. The stand-in does not speak AMQP;
+//! it records who connected, and the protocol header they sent, then hangs up.
+use async_std::{future, task};
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+use std::io::Read;
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rabbit_common::tls::fixture;
+use rpc::{ConnectionConfig, SimpleClient};
+
+const AMQP_PROTOCOL_HEADER: &[u8] = b"AMQP\x00\x00\x09\x01";
+
+// the common name of the client's certificate, along with what it sent
+type Seen = Result<(String, Vec<u8>), String>;
+
+// accept a single connection on a local port, returning the port, and a handle
+// yielding what was seen
+fn stand_in() -> (u16, JoinHandle<Seen>) {
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    acceptor.set_private_key_file(fixture("server.key"), SslFiletype::PEM).unwrap();
+    acceptor.set_certificate_chain_file(fixture("server.pem")).unwrap();
+    acceptor.set_ca_file(fixture("ca.pem")).unwrap();
+    acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    let acceptor = acceptor.build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+        let mut stream = acceptor.accept(stream).map_err(|e| e.to_string())?;
+        let cn = stream.ssl().peer_certificate()
+            .and_then(|cert| {
+                cert.subject_name().entries().next()
+                    .and_then(|entry| String::from_utf8(entry.data().as_slice().to_vec()).ok())
+            })
+            .ok_or("no client certificate")?;
+        let mut header = vec![0; AMQP_PROTOCOL_HEADER.len()];
+        stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+        Ok((cn, header))
+    });
+    (port, handle)
+}
+
+fn config(port: u16, ca: &str, with_identity: bool) -> ConnectionConfig {
+    let mut config = ConnectionConfig::new(format!("amqps://localhost:{}/%2f", port));
+    config.ca_file = Some(fixture(ca));
+    if with_identity {
+        config.cert_file = Some(fixture("client.pem"));
+        config.key_file = Some(fixture("client.key"));
+    }
+    config
+}
+
+// attempt to connect. As the stand-in hangs up rather than speaking AMQP, the
+// attempt itself always fails
+fn connect(config: ConnectionConfig) {
+    let result = task::block_on(future::timeout(Duration::from_secs(10), SimpleClient::connect(config)));
+    assert!(!matches!(result, Ok(Ok(_))), "the stand-in does not speak AMQP");
+}
+
+#[test]
+fn client_certificate_is_presented_over_tls() {
+    let (port, handle) = stand_in();
+    connect(config(port, "ca.pem", true));
+    let (cn, header) = handle.join().unwrap().expect("the handshake should succeed");
+    assert_eq!(cn, "rpc-client");
+    assert_eq!(header, AMQP_PROTOCOL_HEADER);
+}
+
+#[test]
+fn missing_client_certificate_is_rejected() {
+    let (port, handle) = stand_in();
+    connect(config(port, "ca.pem", false));
+    assert!(handle.join().unwrap().is_err());
+}
+
+#[test]
+fn server_not_signed_by_ca_is_rejected() {
+    let (port, handle) = stand_in();
+    connect(config(port, "other_ca.pem", true));
+    assert!(handle.join().unwrap().is_err());
+}
@@ -0,0 +1,36 @@
+//! # rabbit-common
+//!
+//! Pieces shared by each of the tutorial crates:
+//! - connecting to RabbitMq (over TLS when the address is `amqps://`)
+//! - initializing logging
+//! - waiting for, and signalling, shutdown
+//! - the `LogLevel` type
+use lapin::{Connection, ConnectionProperties};
+use std::env;
+use std::sync::Arc;
+
+/// The address of a RabbitMq server running locally, with the default vhost
+pub const LOCALHOST: &str = "amqp://127.0.0.1:5672/%2f";
+
+pub mod log_level;
+pub use log_level::LogLevel;
+
+pub mod logging;
+
+pub mod tls;
+
+pub mod shutdown;
+pub use shutdown::ShutdownHandle;
+
+/// Retrieve the address of the RabbitMq server from the `AMQP_ADDR` environment
+/// variable, falling back to `LOCALHOST` if it is not set.
+pub fn amqp_addr() -> String {
+    env::var("AMQP_ADDR").unwrap_or_else(|_| LOCALHOST.into())
+}
+
+/// Connect to the RabbitMq server at the supplied address, using the TLS
+/// settings from the environment (see `tls::tls_config_from_env`)
+pub async fn connect(addr: &str, properties: ConnectionProperties) -> lapin::Result<Connection> {
+    let config = tls::tls_config_from_env().map_err(|e| lapin::Error::IOError(Arc::new(e)))?;
+    Connection::connect_with_config(addr, properties, config.as_ref()).await
+}
@@ -1,16 +1,21 @@
-use strum::AsRefStr;
-use strum::EnumString;
+//! log_level
+//!
+//! # LogLevel
+//! The standard log levels, shared by the examples which route messages by
+//! level, and by those accepting a `--log-level` flag.
+use strum::{AsRefStr, EnumString};
 
-
-#[derive(Debug, PartialEq, AsRefStr, EnumString)]
+/// A standard log level. Parsing accepts the aliases `warning` and `err`;
+/// `as_ref` always yields the canonical name, which `RUST_LOG` understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumString, AsRefStr)]
 pub enum LogLevel {
     #[strum(serialize = "debug")]
     Debug,
     #[strum(serialize = "info")]
     Info,
-    #[strum(serialize = "warn")]
+    #[strum(to_string = "warn", serialize = "warning")]
     Warn,
-    #[strum(serialize = "error")]
+    #[strum(to_string = "error", serialize = "err")]
     Error
 }
 
@@ -23,15 +28,17 @@ mod tests {
     fn loglevel_given_str_converts() {
         for (levelstr, loglevel) in &[
             ("debug",LogLevel::Debug),
-            ("info", LogLevel::Info), 
-            ("warn", LogLevel::Warn), 
-            ("error", LogLevel::Error)
+            ("info", LogLevel::Info),
+            ("warn", LogLevel::Warn),
+            ("warning", LogLevel::Warn),
+            ("error", LogLevel::Error),
+            ("err", LogLevel::Error)
             ] {
             let level = LogLevel::from_str(levelstr);
             assert_eq!( level.unwrap(), *loglevel);
             }
         }
-       
+
 
     #[test]
     fn loglevel_given_bad_str_fails() {
@@ -43,14 +50,14 @@ mod tests {
     fn loglevel_to_string() {
         for (levelstr, loglevel) in &[
             ("debug",LogLevel::Debug),
-            ("info", LogLevel::Info), 
-            ("warn", LogLevel::Warn), 
+            ("info", LogLevel::Info),
+            ("warn", LogLevel::Warn),
             ("error", LogLevel::Error)
             ]
         {
             let level = loglevel.as_ref();
             assert_eq!( &level, levelstr);
         }
-        
+
     }
-}
\ No newline at end of file
+}
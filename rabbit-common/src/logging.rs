@@ -0,0 +1,24 @@
+//! logging
+//!
+//! # Logging
+//! Initialize tracing, with the level taken from `RUST_LOG` where it is set.
+use std::env;
+
+use crate::LogLevel;
+
+/// Initialize tracing, logging at the supplied level unless `RUST_LOG` is set.
+pub fn init(default: LogLevel) {
+    init_with_level(None, default)
+}
+
+/// Initialize tracing. An explicitly requested level (eg from a `--log-level`
+/// flag) takes precedence over `RUST_LOG`, which in turn takes precedence over
+/// the supplied default.
+pub fn init_with_level(level: Option<LogLevel>, default: LogLevel) {
+    if let Some(level) = level {
+        env::set_var("RUST_LOG", level.as_ref());
+    } else if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", default.as_ref());
+    }
+    tracing_subscriber::fmt::init();
+}
@@ -0,0 +1,255 @@
+//! shutdown
+//!
+//! # Shutdown
+//! A handle used to ask a running server to stop, which may be cloned and
+//! triggered from any thread - including a signal handler.
+//!
+//! `listen` triggers a shutdown on SIGINT or SIGTERM, or when the user types
+//! `q` on stdin. A consumer then stops cleanly via `close`, which cancels it,
+//! waits for the deliveries it is working on (tracked by `InFlight`) to be
+//! acked, and closes the channel and connection.
+use async_channel::{bounded, Receiver, Sender};
+use lapin::{options::BasicCancelOptions, Channel, Connection};
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tracing::info;
+
+/// Handle used to request that a server stop serving.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    sender: Sender<()>,
+}
+
+impl ShutdownHandle {
+    /// Request a shutdown. Calling this more than once is harmless.
+    pub fn shutdown(&self) {
+        // closing the channel wakes every listener at once
+        self.sender.close();
+    }
+
+    /// Has a shutdown been requested?
+    pub fn is_shutdown(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+/// The receiving half of a ShutdownHandle, which completes once a shutdown has
+/// been requested.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    receiver: Receiver<()>,
+}
+
+impl ShutdownSignal {
+    /// Wait until a shutdown has been requested
+    pub async fn wait(&self) {
+        // nothing is ever sent, so this only returns once the channel is closed
+        let _ = self.receiver.recv().await;
+    }
+
+    /// Has a shutdown been requested?
+    pub fn is_shutdown(&self) -> bool {
+        self.receiver.is_closed()
+    }
+}
+
+/// Create a connected ShutdownHandle and ShutdownSignal
+pub fn channel() -> (ShutdownHandle, ShutdownSignal) {
+    let (sender, receiver) = bounded(1);
+    (ShutdownHandle { sender }, ShutdownSignal { receiver })
+}
+
+/// Request a shutdown via the supplied handle on SIGINT (ctrl-c) or SIGTERM.
+/// This may only be called once per process.
+pub fn on_signal(handle: ShutdownHandle) -> io::Result<()> {
+    ctrlc::set_handler(move || handle.shutdown())
+        .map_err(io::Error::other)
+}
+
+/// Request a shutdown via the supplied handle when the user types `q`, `quit`
+/// or `exit`. Stdin is read on a thread of its own, which simply stops once
+/// stdin is closed (eg when running under systemd, or in a container), leaving
+/// signals as the only way to shut down.
+pub fn on_quit(handle: ShutdownHandle) {
+    println!("Type q or exit (or press ctrl-c) to quit");
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        watch(stdin.lock(), &handle)
+    });
+}
+
+/// Listen for a request to shut down, via SIGINT, SIGTERM or stdin (see
+/// `on_signal` and `on_quit`).
+pub fn listen() -> io::Result<ShutdownSignal> {
+    let (handle, signal) = channel();
+    on_signal(handle.clone())?;
+    on_quit(handle);
+    Ok(signal)
+}
+
+// read lines until one asks to quit, or the input is exhausted
+fn watch(input: impl BufRead, handle: &ShutdownHandle) {
+    for line in input.lines() {
+        match line {
+            Ok(line) if is_quit(&line) => {
+                handle.shutdown();
+                return;
+            }
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+fn is_quit(input: &str) -> bool {
+    let input = input.trim();
+    input == "q" || input == "quit" || input == "exit"
+}
+
+/// Counts the deliveries being worked on, so that a consumer may wait for them
+/// to be acked before closing its channel.
+#[derive(Debug, Clone)]
+pub struct InFlight {
+    count: Arc<AtomicUsize>,
+    // notified each time the count drops to zero
+    idle_sender: Sender<()>,
+    idle_receiver: Receiver<()>,
+}
+
+impl Default for InFlight {
+    fn default() -> Self {
+        let (idle_sender, idle_receiver) = bounded(1);
+        Self {
+            count: Arc::new(AtomicUsize::new(0)),
+            idle_sender,
+            idle_receiver,
+        }
+    }
+}
+
+impl InFlight {
+    /// Create a tracker with nothing in flight
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the start of work on a delivery, which lasts until the returned
+    /// guard is dropped
+    pub fn start(&self) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard { in_flight: self.clone() }
+    }
+
+    /// The number of deliveries being worked on
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Is nothing being worked on?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wait until no deliveries are being worked on
+    pub async fn wait_idle(&self) {
+        while !self.is_empty() {
+            // a notification is kept until received, so one sent between the
+            // check above and this wait is not lost
+            let _ = self.idle_receiver.recv().await;
+        }
+    }
+}
+
+/// Marks a delivery as being worked on, until dropped
+#[derive(Debug)]
+pub struct InFlightGuard {
+    in_flight: InFlight,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.in_flight.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _ = self.in_flight.idle_sender.try_send(());
+        }
+    }
+}
+
+/// Stop consuming cleanly: cancel the consumer, so that no further deliveries
+/// arrive, wait for those in flight to be acked, then close the channel and
+/// connection. Deliveries which were prefetched but not yet started are
+/// returned to the queue by the broker.
+pub async fn close(
+    conn: &Connection,
+    channel: &Channel,
+    consumer_tag: &str,
+    in_flight: &InFlight,
+) -> lapin::Result<()> {
+    info!(consumer_tag, "cancelling consumer");
+    channel.basic_cancel(consumer_tag, BasicCancelOptions::default()).await?;
+    info!(in_flight = in_flight.len(), "waiting for deliveries in flight");
+    in_flight.wait_idle().await;
+    channel.close(200, "shutting down").await?;
+    conn.close(200, "shutting down").await?;
+    info!("closed channel and connection");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::{future, task};
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    #[test]
+    fn shutdown_wakes_signal() {
+        let (handle, signal) = channel();
+        assert!(!signal.is_shutdown());
+        let waiter = task::spawn({
+            let signal = signal.clone();
+            async move { signal.wait().await }
+        });
+        handle.clone().shutdown();
+        handle.shutdown();
+        task::block_on(waiter);
+        assert!(signal.is_shutdown());
+        assert!(handle.is_shutdown());
+    }
+
+    #[test]
+    fn watch_given_quit_shuts_down() {
+        let (handle, signal) = channel();
+        watch(Cursor::new("hello\n  exit \nmore\n"), &handle);
+        assert!(signal.is_shutdown());
+    }
+
+    #[test]
+    fn watch_given_eof_returns_without_shutting_down() {
+        let (handle, signal) = channel();
+        watch(Cursor::new(""), &handle);
+        watch(Cursor::new("hello\nqueue\n"), &handle);
+        assert!(!signal.is_shutdown());
+    }
+
+    #[test]
+    fn wait_idle_waits_for_guards() {
+        let in_flight = InFlight::new();
+        task::block_on(in_flight.wait_idle());
+
+        let first = in_flight.start();
+        let second = in_flight.start();
+        assert_eq!(in_flight.len(), 2);
+        let waiter = task::spawn({
+            let in_flight = in_flight.clone();
+            async move { in_flight.wait_idle().await }
+        });
+        drop(first);
+        let waited = task::block_on(future::timeout(Duration::from_millis(50), in_flight.wait_idle()));
+        assert!(waited.is_err(), "a delivery is still in flight");
+        drop(second);
+        task::block_on(waiter);
+        assert!(in_flight.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! tls
+//!
+//! # Tls
+//! Settings for connecting to RabbitMq over TLS (`amqps://`), verifying the
+//! server against a CA bundle, and optionally authenticating with a client
+//! certificate.
+//!
+//! Lapin expects the client identity as a PKCS#12 archive, so the PEM encoded
+//! certificate and key are bundled into one in memory.
+use lapin::tcp::{OwnedIdentity, OwnedTLSConfig};
+use openssl::{pkcs12::Pkcs12, pkey::PKey, stack::Stack, x509::X509};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Environment variable holding the path to the CA bundle (PEM)
+pub const ENV_CA_FILE: &str = "AMQP_CA_FILE";
+/// Environment variable holding the path to the client certificate (PEM)
+pub const ENV_CERT_FILE: &str = "AMQP_CERT_FILE";
+/// Environment variable holding the path to the client key (PEM)
+pub const ENV_KEY_FILE: &str = "AMQP_KEY_FILE";
+
+// the identity never leaves memory, but it must be protected by some password
+const IDENTITY_PASSWORD: &str = "rabbit";
+
+/// Build the TLS settings given the paths of the CA bundle, and of the client
+/// certificate and key. The certificate and key must be supplied together.
+pub fn tls_config(
+    ca_file: Option<&Path>,
+    cert_file: Option<&Path>,
+    key_file: Option<&Path>,
+) -> io::Result<OwnedTLSConfig> {
+    let cert_chain = match ca_file {
+        Some(path) => Some(read_to_string(path)?),
+        None => None,
+    };
+    let identity = match (cert_file, key_file) {
+        (Some(cert), Some(key)) => Some(identity_from_pem(&read(cert)?, &read(key)?)?),
+        (None, None) => None,
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a client certificate and key must be supplied together",
+        )),
+    };
+    Ok(OwnedTLSConfig { identity, cert_chain })
+}
+
+/// Build the TLS settings from the paths held by the `AMQP_CA_FILE`,
+/// `AMQP_CERT_FILE` and `AMQP_KEY_FILE` environment variables, any of which
+/// may be unset.
+pub fn tls_config_from_env() -> io::Result<OwnedTLSConfig> {
+    let path = |name| env::var_os(name).map(PathBuf::from);
+    let (ca_file, cert_file, key_file) = (path(ENV_CA_FILE), path(ENV_CERT_FILE), path(ENV_KEY_FILE));
+    tls_config(ca_file.as_deref(), cert_file.as_deref(), key_file.as_deref())
+}
+
+/// Bundle a PEM encoded certificate (optionally followed by its chain) and
+/// private key into the PKCS#12 identity expected by lapin
+pub fn identity_from_pem(cert_pem: &[u8], key_pem: &[u8]) -> io::Result<OwnedIdentity> {
+    let invalid = |e: openssl::error::ErrorStack| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("invalid client identity: {}", e))
+    };
+    let mut certs = X509::stack_from_pem(cert_pem).map_err(invalid)?.into_iter();
+    let cert = certs.next().ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the client certificate file holds no certificates",
+    ))?;
+    let key = PKey::private_key_from_pem(key_pem).map_err(invalid)?;
+    let mut chain = Stack::new().map_err(invalid)?;
+    for ca in certs {
+        chain.push(ca).map_err(invalid)?;
+    }
+    let pkcs12 = Pkcs12::builder()
+        .name("rabbit-client")
+        .pkey(&key)
+        .cert(&cert)
+        .ca(chain)
+        .build2(IDENTITY_PASSWORD)
+        .map_err(invalid)?;
+    Ok(OwnedIdentity {
+        der: pkcs12.to_der().map_err(invalid)?,
+        password: IDENTITY_PASSWORD.to_string(),
+    })
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path).map_err(|e| unreadable(path, e))
+}
+
+fn read_to_string(path: &Path) -> io::Result<String> {
+    fs::read_to_string(path).map_err(|e| unreadable(path, e))
+}
+
+fn unreadable(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("unable to read {}: {}", path.display(), e))
+}
+
+/// Path of one of the test certificates in `tests/fixtures/tls`, so that the
+/// tests of the crates which connect over TLS share the one set
+#[cfg(any(test, feature = "test-fixtures"))]
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls").join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_from_pem_bundles_cert_and_key() {
+        let identity = identity_from_pem(
+            &fs::read(fixture("client.pem")).unwrap(),
+            &fs::read(fixture("client.key")).unwrap(),
+        ).unwrap();
+        let parsed = Pkcs12::from_der(&identity.der).unwrap().parse2(&identity.password).unwrap();
+        let cert = parsed.cert.unwrap();
+        let cn = cert.subject_name().entries().next().unwrap().data().as_slice().to_vec();
+        let cn = String::from_utf8(cn).unwrap();
+        assert_eq!(cn, "rpc-client");
+    }
+
+    #[test]
+    fn tls_config_reads_ca_bundle() {
+        let config = tls_config(Some(&fixture("ca.pem")), None, None).unwrap();
+        assert!(config.cert_chain.unwrap().contains("BEGIN CERTIFICATE"));
+        assert!(config.identity.is_none());
+    }
+
+    #[test]
+    fn tls_config_given_cert_without_key_fails() {
+        let result = tls_config(None, Some(&fixture("client.pem")), None);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn tls_config_given_missing_file_fails() {
+        let err = tls_config(Some(&fixture("missing.pem")), None, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("missing.pem"));
+    }
+}
@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+# Regenerate the certificates used by the TLS tests. These are test fixtures
+# only - never use them to secure a real broker.
+set -euo pipefail
+cd "$(dirname "$0")"
+DAYS=36500
+
+# certificate authority
+openssl req -x509 -newkey rsa:2048 -nodes -days $DAYS \
+    -keyout ca.key -out ca.pem -subj "/CN=rpc-test-ca"
+
+# server, valid for localhost
+openssl req -newkey rsa:2048 -nodes -keyout server.key -out server.csr -subj "/CN=localhost"
+printf "subjectAltName=DNS:localhost,IP:127.0.0.1\n" > server.ext
+openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial \
+    -days $DAYS -out server.pem -extfile server.ext
+
+# client
+openssl req -newkey rsa:2048 -nodes -keyout client.key -out client.csr -subj "/CN=rpc-client"
+printf "extendedKeyUsage=clientAuth\n" > client.ext
+openssl x509 -req -in client.csr -CA ca.pem -CAkey ca.key -CAcreateserial \
+    -days $DAYS -out client.pem -extfile client.ext
+
+# a second authority, which signed nothing we trust
+openssl req -x509 -newkey rsa:2048 -nodes -days $DAYS \
+    -keyout other_ca.key -out other_ca.pem -subj "/CN=rpc-other-ca"
+
+rm -f *.csr *.ext *.srl ca.key other_ca.key